@@ -0,0 +1,130 @@
+use crate::entities::season::SeasonNumber;
+
+use anyhow::{anyhow, Result};
+use rocket::figment::providers::Env;
+use rocket::figment::Figment;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Everything that can change between deployments, read from `Rocket.toml` and the environment
+/// along with Rocket's own settings, so `sled_path` can be set as either
+///
+/// ```toml
+/// [default]
+/// sled_path = "/var/lib/cardstock"
+/// ```
+///
+/// or `CARDSTOCK_SLED_PATH=/var/lib/cardstock`. `ROCKET_SLED_PATH` works too, and per-profile
+/// tables like `[debug]` override `[default]` as they do for Rocket's settings. Rocket's own
+/// settings can't be set through `CARDSTOCK_*`, so `CARDSTOCK_PORT` is ignored.
+#[derive(Deserialize)]
+pub struct CardstockConfig {
+    /// where the sled database is kept, the one setting without a default. `CARDSTOCK_SLED_V1`
+    /// is still accepted for it.
+    #[serde(alias = "sled_v1")]
+    pub sled_path: PathBuf,
+
+    /// `files`, `files:<directory>`, `snapshots:<directory>` or `chronicler:<base url>`, see
+    /// [`crate::source::IngestSource`]
+    #[serde(default = "default_ingest_source")]
+    pub ingest_source: String,
+    /// where `files` without a directory reads `idols.json`, `games.json` and `teams.json` from
+    #[serde(default = "default_data_directory")]
+    pub data_directory: PathBuf,
+
+    #[serde(default = "default_chronicler_base")]
+    pub chronicler_base: String,
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    #[serde(default = "default_chronicler_requests_per_second")]
+    pub chronicler_requests_per_second: f64,
+    /// how many players are fetched from chronicler at once
+    #[serde(default = "default_ingest_concurrency")]
+    pub ingest_concurrency: usize,
+    /// whether to keep the idol boards as chronicler returned them, in `idols_raw_v2`
+    #[serde(default)]
+    pub keep_raw_idol_boards: bool,
+
+    /// games from this season and earlier are left out of `games_v1`, since there were no idol
+    /// boards to place on them. Given in display numbering, like the rest of the site.
+    #[serde(default = "default_zeroth_season_with_idol_board")]
+    pub zeroth_season_with_idol_board: SeasonNumber,
+}
+
+/// the settings `CARDSTOCK_*` variables can set, which are the fields of [`CardstockConfig`]
+const KEYS: &[&str] = &[
+    "sled_path",
+    "sled_v1",
+    "ingest_source",
+    "data_directory",
+    "chronicler_base",
+    "user_agent",
+    "chronicler_requests_per_second",
+    "ingest_concurrency",
+    "keep_raw_idol_boards",
+    "zeroth_season_with_idol_board",
+];
+
+impl CardstockConfig {
+    /// Rocket's figment, with `CARDSTOCK_*` variables for Cardstock's own settings merged in over
+    /// it
+    pub fn figment() -> Figment {
+        rocket::Config::figment().merge(Env::prefixed("CARDSTOCK_").only(KEYS).global())
+    }
+
+    /// catches settings that would otherwise only fail once the ingest is underway, other than
+    /// the ones [`crate::source::IngestSource::from_config`] checks
+    pub fn validate(&self) -> Result<()> {
+        if self.ingest_concurrency == 0 {
+            return Err(anyhow!("ingest_concurrency must be at least 1"));
+        }
+
+        Ok(())
+    }
+}
+
+fn default_ingest_source() -> String {
+    "files".into()
+}
+
+fn default_data_directory() -> PathBuf {
+    "data".into()
+}
+
+fn default_chronicler_base() -> String {
+    "https://api.sibr.dev/chronicler".into()
+}
+
+fn default_user_agent() -> String {
+    "cardstock/0.0 (lofty@sibr.dev)".into()
+}
+
+fn default_chronicler_requests_per_second() -> f64 {
+    5.0
+}
+
+fn default_ingest_concurrency() -> usize {
+    4
+}
+
+fn default_zeroth_season_with_idol_board() -> SeasonNumber {
+    SeasonNumber::from_storage(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cardstock_variables_only_set_cardstock_settings() {
+        std::env::set_var("CARDSTOCK_SLED_PATH", "/var/lib/cardstock");
+        std::env::set_var("CARDSTOCK_PORT", "1");
+
+        let figment = CardstockConfig::figment();
+        let config: CardstockConfig = figment.extract().unwrap();
+        let rocket_config: rocket::Config = figment.extract().unwrap();
+
+        assert_eq!(config.sled_path, PathBuf::from("/var/lib/cardstock"));
+        assert_ne!(rocket_config.port, 1);
+    }
+}
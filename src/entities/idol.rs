@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -17,11 +18,22 @@ pub struct Idol {
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct IdolsClass {
-    #[serde(rename = "data")]
-    pub data: Data,
+    /// missing for boards converted from the legacy `IdolArray` format, which didn't have it
+    #[serde(rename = "data", default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Data>,
 
     #[serde(rename = "idols")]
     pub idols: Vec<Uuid>,
+
+    /// not part of chronicler's data: when this arrangement of players was replaced, once any
+    /// consecutive duplicates have been collapsed into this board
+    #[serde(rename = "validTo", default, skip_serializing_if = "Option::is_none")]
+    pub valid_to: Option<DateTime<Utc>>,
+
+    /// not part of chronicler's data: the number of fans idoling each player, in the same order as
+    /// `idols`, kept from boards in the legacy `IdolArray` format
+    #[serde(rename = "totals", default, skip_serializing_if = "Option::is_none")]
+    pub totals: Option<Vec<Option<i64>>>,
 }
 
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
@@ -38,3 +50,17 @@ pub enum Idols {
 
     IdolsClass(IdolsClass),
 }
+
+impl From<Idols> for IdolsClass {
+    fn from(idols: Idols) -> IdolsClass {
+        match idols {
+            Idols::IdolArray(array) => IdolsClass {
+                data: None,
+                totals: Some(array.iter().map(|idol| idol.total).collect()),
+                idols: array.into_iter().map(|idol| idol.player_id).collect(),
+                valid_to: None,
+            },
+            Idols::IdolsClass(idols_class) => idols_class,
+        }
+    }
+}
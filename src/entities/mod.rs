@@ -1,3 +1,4 @@
 pub mod idol;
 pub mod player;
+pub mod season;
 pub mod team;
@@ -1,13 +1,41 @@
-use crate::entities::team::TeamDisplayable;
+use crate::entities::team::{RosterStatus, TeamDisplayable};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+#[derive(Serialize)]
 pub struct PlayerDisplayable {
     pub id: Uuid,
     pub name: String,
     pub team: TeamDisplayable,
+    /// where the player was on the team's roster, if the team's version at the time is cached
+    pub roster: Option<RosterStatus>,
     pub deceased: bool,
     pub ego: i8,
+    /// only known for boards from the early seasons, when the idol endpoint included fan totals
+    pub fans: Option<Fans>,
+}
+
+impl PlayerDisplayable {
+    /// a stand-in for a player on a board who isn't in the cache
+    pub fn unknown(id: Uuid) -> PlayerDisplayable {
+        PlayerDisplayable {
+            id,
+            name: "Unknown player".into(),
+            team: TeamDisplayable::unknown(),
+            roster: None,
+            deceased: false,
+            ego: 0,
+            fans: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Fans {
+    pub total: i64,
+    /// compared with the previous board, if the player was on it
+    pub change: Option<i64>,
 }
 
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
@@ -25,3 +53,68 @@ pub struct PlayerData {
     #[serde(rename = "permAttr")]
     pub permanent_attributes: Option<Vec<String>>,
 }
+
+impl PlayerData {
+    /// the player's ego level, from the `EGO1` to `EGO4` permanent attributes, or 0 if they have
+    /// none of them
+    pub fn ego(&self) -> i8 {
+        self.permanent_attributes
+            .iter()
+            .flatten()
+            .filter_map(|attribute| attribute.strip_prefix("EGO"))
+            .filter_map(|level| level.parse::<i8>().ok())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// What `/search` finds players by, and where their page and graph place them on the boards,
+/// rebuilt from `players_v1` and `idols_v2` after every ingest.
+#[derive(Default, Serialize, Deserialize)]
+pub struct PlayerIndexEntry {
+    /// every name the player has had, oldest first
+    pub names: Vec<String>,
+    /// the seasons the player was on an idol board in, in order, numbered the way
+    /// [`crate::entities::season::SeasonNumber::storage`] does
+    pub seasons: Vec<i16>,
+    /// every board the player was on, oldest first
+    #[serde(default)]
+    pub boards: Vec<BoardAppearance>,
+    /// the most players on any of those boards, which is as far down as the graph goes
+    #[serde(default)]
+    pub largest_board: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BoardAppearance {
+    pub timestamp: DateTime<Utc>,
+    /// counted from 1
+    pub rank: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::player;
+
+    fn ego_with(attributes: Option<&[&str]>) -> i8 {
+        let mut player = player(Uuid::from_u128(1), "Player");
+        player.permanent_attributes =
+            attributes.map(|attributes| attributes.iter().map(|a| a.to_string()).collect());
+        player.ego()
+    }
+
+    #[test]
+    fn ego_is_the_highest_level_held() {
+        assert_eq!(ego_with(Some(&["EGO1"])), 1);
+        assert_eq!(ego_with(Some(&["EGO3", "EGO1", "EGO2"])), 3);
+        assert_eq!(ego_with(Some(&["SHELLED", "EGO4", "PERK"])), 4);
+    }
+
+    #[test]
+    fn other_attributes_are_no_ego() {
+        assert_eq!(ego_with(None), 0);
+        assert_eq!(ego_with(Some(&[])), 0);
+        assert_eq!(ego_with(Some(&["SHELLED", "EGOIST", "ego2"])), 0);
+    }
+}
@@ -0,0 +1,160 @@
+use rocket::request::FromParam;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+#[derive(Serialize, Deserialize)]
+pub struct Era {
+    pub name: String,
+    pub seasons: Vec<Season>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Season {
+    pub index: SeasonNumber,
+    pub start_date: String,
+    pub end_date: String,
+    pub election_offset: Option<String>,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+/// Something shown on the idol board in-game that only made sense for a particular season.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Annotation {
+    /// a red line under the players who would be affected when the board was drawn
+    RedLine { after_rank: usize },
+    /// the feedback icon shown next to these positions
+    Feedback { ranks: Vec<usize> },
+    /// the weather icon shown next to these positions
+    Weather { ranks: Vec<usize> },
+    /// the noodle, drawn under the position given by each board's `strictlyConfidential`
+    Noodle,
+}
+
+/// A season, kept as chronicler and `games_v1` number them, from 0. Everywhere a person sees a
+/// season number, including URLs, the JSON API and `data/elections.json`, it counts from 1, so
+/// this only converts between the two at those edges.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SeasonNumber(i16);
+
+impl SeasonNumber {
+    pub const fn from_storage(season: i16) -> SeasonNumber {
+        SeasonNumber(season)
+    }
+
+    /// `None` for season numbers below 1, which never existed
+    pub fn from_display(season: i16) -> Option<SeasonNumber> {
+        (season >= 1).then_some(SeasonNumber(season - 1))
+    }
+
+    pub fn storage(self) -> i16 {
+        self.0
+    }
+
+    pub fn display(self) -> i16 {
+        self.0 + 1
+    }
+
+    pub fn previous(self) -> SeasonNumber {
+        SeasonNumber(self.0 - 1)
+    }
+
+    pub fn next(self) -> SeasonNumber {
+        SeasonNumber(self.0 + 1)
+    }
+}
+
+impl fmt::Display for SeasonNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display())
+    }
+}
+
+impl Serialize for SeasonNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i16(self.display())
+    }
+}
+
+impl<'de> Deserialize<'de> for SeasonNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let season = i16::deserialize(deserializer)?;
+        SeasonNumber::from_display(season)
+            .ok_or_else(|| serde::de::Error::custom(format!("there was no season {}", season)))
+    }
+}
+
+/// `/season/6` is the sixth season, which chronicler calls season 5.
+impl<'a> FromParam<'a> for SeasonNumber {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        param
+            .parse()
+            .ok()
+            .and_then(SeasonNumber::from_display)
+            .ok_or(param)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_season_is_zero_in_storage() {
+        let season = SeasonNumber::from_display(1).unwrap();
+        assert_eq!(season.storage(), 0);
+        assert_eq!(season.display(), 1);
+        assert_eq!(season, SeasonNumber::from_storage(0));
+    }
+
+    #[test]
+    fn no_season_before_the_first() {
+        assert_eq!(SeasonNumber::from_display(0), None);
+        assert_eq!(SeasonNumber::from_display(-1), None);
+        assert!(SeasonNumber::from_param("0").is_err());
+        assert!(SeasonNumber::from_param("six").is_err());
+    }
+
+    #[test]
+    fn url_season_is_display_numbered() {
+        let season = SeasonNumber::from_param("6").unwrap();
+        assert_eq!(season.storage(), 5);
+        assert_eq!(season.to_string(), "6");
+    }
+
+    #[test]
+    fn display_and_storage_round_trip() {
+        for storage in [0, 4, 5, 23] {
+            let season = SeasonNumber::from_storage(storage);
+            assert_eq!(SeasonNumber::from_display(season.display()), Some(season));
+        }
+    }
+
+    #[test]
+    fn adjacent_seasons() {
+        let season = SeasonNumber::from_storage(5);
+        assert_eq!(season.previous().display(), 5);
+        assert_eq!(season.next().display(), 7);
+        assert_eq!(season.next().previous(), season);
+    }
+
+    #[test]
+    fn json_uses_display_numbering() {
+        let season = SeasonNumber::from_storage(5);
+        assert_eq!(serde_json::to_string(&season).unwrap(), "6");
+        assert_eq!(serde_json::from_str::<SeasonNumber>("6").unwrap(), season);
+        assert!(serde_json::from_str::<SeasonNumber>("0").is_err());
+    }
+
+    #[test]
+    fn elections_index_is_display_numbered() {
+        let season: Season = serde_json::from_str(
+            r#"{"index": 6, "start_date": "", "end_date": "", "election_offset": null}"#,
+        )
+        .unwrap();
+        assert_eq!(season.index.storage(), 5);
+    }
+}
@@ -1,30 +1,225 @@
+use crate::entities::season::SeasonNumber;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize)]
+pub struct TeamData {
+    pub id: Uuid,
+    #[serde(rename = "fullName")]
+    pub full_name: String,
+    #[serde(rename = "mainColor")]
+    pub colour: String,
+    #[serde(rename = "secondaryColor")]
+    pub secondary_colour: Option<String>,
+    pub emoji: String,
+    pub slogan: Option<String>,
+
+    /// the batters, in order
+    #[serde(default)]
+    pub lineup: Vec<Uuid>,
+    /// the pitchers, in order
+    #[serde(default)]
+    pub rotation: Vec<Uuid>,
+    /// players who aren't playing, from season 12 on
+    #[serde(default)]
+    pub shadows: Vec<Uuid>,
+    /// what the shadows were split into before season 12
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bench: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bullpen: Vec<Uuid>,
+
+    /// the tarot card the team drew, or -1 if it hasn't drawn one
+    pub card: Option<i64>,
+    /// free-form, so kept as chronicler has it
+    #[serde(default)]
+    pub state: serde_json::Value,
+}
+
+impl TeamData {
+    /// whether `player` was playing for the team or in its shadows, or `None` if they weren't on
+    /// the roster at all
+    pub fn roster_status(&self, player: Uuid) -> Option<RosterStatus> {
+        if self.lineup.contains(&player) || self.rotation.contains(&player) {
+            Some(RosterStatus::Active)
+        } else if self.shadows().any(|id| *id == player) {
+            Some(RosterStatus::Shadowed)
+        } else {
+            None
+        }
+    }
+
+    pub fn shadows(&self) -> impl Iterator<Item = &Uuid> {
+        self.shadows
+            .iter()
+            .chain(self.bench.iter())
+            .chain(self.bullpen.iter())
+    }
+
+    /// the number of the tarot card the team drew, if it has drawn one
+    pub fn tarot_card(&self) -> Option<i64> {
+        self.card.filter(|card| *card >= 0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RosterStatus {
+    Active,
+    Shadowed,
+}
+
+impl RosterStatus {
+    pub fn title(&self) -> &'static str {
+        match self {
+            RosterStatus::Active => "Active",
+            RosterStatus::Shadowed => "Shadowed",
+        }
+    }
+
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            RosterStatus::Active => "active",
+            RosterStatus::Shadowed => "shadowed",
+        }
+    }
+}
+
+/// What a team's page lists, rebuilt from `idols_v2` after every ingest so the page doesn't have
+/// to go through every board. Seasons are numbered the way [`SeasonNumber::storage`] does.
+#[derive(Default, Serialize, Deserialize)]
+pub struct TeamIndexEntry {
+    /// the seasons one of the team's players was on a board in, in order
+    pub seasons: Vec<TeamIndexSeason>,
+    /// every player who was on a board while on the team, in the order they first were
+    pub players: Vec<TeamIndexPlayer>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TeamIndexSeason {
+    pub season: i16,
+    /// boards the team's players appeared on, counting each player separately
+    pub appearances: usize,
+    /// different players from the team on the season's boards
+    pub players: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TeamIndexPlayer {
+    pub id: Uuid,
+    /// the player's name at their latest appearance for the team
+    pub name: String,
+    /// boards the player appeared on while on the team
+    pub appearances: usize,
+    pub best_rank: usize,
+    /// where the player was on the roster at their latest appearance for the team
+    pub roster: Option<RosterStatus>,
+    pub seasons: Vec<i16>,
+}
+
+impl TeamIndexEntry {
+    /// counts one of the team's players being at `rank` on a board, which has to be no earlier
+    /// than the boards already counted
+    pub fn add_appearance(
+        &mut self,
+        season: SeasonNumber,
+        player: Uuid,
+        name: String,
+        rank: usize,
+        roster: Option<RosterStatus>,
+    ) {
+        let season = season.storage();
+        if self.seasons.last().map(|last| last.season) != Some(season) {
+            self.seasons.push(TeamIndexSeason {
+                season,
+                appearances: 0,
+                players: 0,
+            });
+        }
+        let team_season = self.seasons.last_mut().unwrap();
+        team_season.appearances += 1;
+
+        match self.players.iter_mut().find(|entry| entry.id == player) {
+            Some(entry) => {
+                entry.name = name;
+                entry.roster = roster;
+                entry.appearances += 1;
+                entry.best_rank = entry.best_rank.min(rank);
+                if entry.seasons.last() != Some(&season) {
+                    entry.seasons.push(season);
+                    team_season.players += 1;
+                }
+            }
+            None => {
+                self.players.push(TeamIndexPlayer {
+                    id: player,
+                    name,
+                    appearances: 1,
+                    best_rank: rank,
+                    roster,
+                    seasons: vec![season],
+                });
+                team_season.players += 1;
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
 pub struct TeamDisplayable {
+    /// `None` for the null team and for teams missing from the cache, which have no page
+    pub id: Option<Uuid>,
     pub name: String,
     pub colour: String,
+    pub secondary_colour: String,
     pub emoji: String,
+    /// empty if the team doesn't have one
+    pub slogan: String,
 }
 
 impl TeamDisplayable {
-    pub fn new(name: String, colour: String, emoji: String) -> TeamDisplayable {
-        let emoji = emoji
+    pub fn new(team: &TeamData) -> TeamDisplayable {
+        let emoji = team
+            .emoji
             .strip_prefix("0x")
             .and_then(|hex| u32::from_str_radix(hex, 16).ok())
             .and_then(|s| char::try_from(s).ok())
             .map(|c| c.to_string())
-            .unwrap_or(emoji);
+            .unwrap_or_else(|| team.emoji.clone());
 
         TeamDisplayable {
-            name,
-            colour,
+            id: Some(team.id),
+            name: team.full_name.clone(),
+            colour: team.colour.clone(),
+            secondary_colour: team
+                .secondary_colour
+                .clone()
+                .unwrap_or_else(|| team.colour.clone()),
             emoji,
+            slogan: team.slogan.clone().unwrap_or_default(),
+        }
+    }
+
+    /// what a player with no `leagueTeamId` is shown as playing for
+    pub fn null() -> TeamDisplayable {
+        TeamDisplayable {
+            id: None,
+            name: "nullteam".into(),
+            colour: "#999999".into(),
+            secondary_colour: "#999999".into(),
+            emoji: "❓".into(),
+            slogan: String::new(),
         }
     }
 
-    fn twemoji(&self) -> String {
-        self.emoji
-            .chars()
-            .map(|c| format!("{:x}", u32::from(c)))
-            .intersperse("-".into())
-            .collect()
+    pub fn unknown() -> TeamDisplayable {
+        TeamDisplayable {
+            id: None,
+            name: "Unknown team".into(),
+            colour: "#999999".into(),
+            secondary_colour: "#999999".into(),
+            emoji: "❓".into(),
+            slogan: String::new(),
+        }
     }
 }
@@ -1,221 +1,323 @@
-#![feature(iter_intersperse)]
-
+mod config;
 mod entities;
 mod routes;
+mod source;
+mod store;
+#[cfg(test)]
+pub(crate) mod test_support;
 
+use crate::config::CardstockConfig;
 use crate::entities::idol;
-use crate::entities::player::PlayerData;
+use crate::entities::player::{BoardAppearance, PlayerData, PlayerIndexEntry};
+use crate::entities::season::SeasonNumber;
+use crate::entities::team::{TeamData, TeamIndexEntry};
+use crate::source::IngestSource;
+use crate::store::{IngestCheckpoint, LookupError, PendingPage, Store};
 use chrono::{DateTime, Utc};
-use reqwest::Client;
+use futures::stream::{FuturesUnordered, StreamExt};
 use rocket::fairing::AdHoc;
-use rocket::{launch, routes};
-use serde::{Deserialize, Serialize};
-use sled::{Db, Tree};
-use std::fs;
+use rocket::{routes, Build, Rocket};
+use serde::Deserialize;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use uuid::Uuid;
-use zerocopy::{AsBytes, BigEndian, FromBytes, I16, I64};
 
-lazy_static::lazy_static! {
-    static ref DB: Db = sled::Config::default()
-        .path(std::env::var_os("CARDSTOCK_SLED_V1").expect("CARDSTOCK_SLED_V1 not set in environment"))
-        .use_compression(true)
-        .open()
-        .unwrap();
+const BEGINNING_OF_TIME: &str = "2020-01-01T00:00:00Z";
+const END_OF_TIME: &str = "2099-01-01T00:00:00Z";
 
-    static ref CLIENT: Client = Client::builder()
-        .user_agent("cardstock/0.0 (lofty@sibr.dev)")
-        .build()
-        .unwrap();
+/// Reads and checks the config and opens the database, so that a bad setting stops the server
+/// from starting rather than turning up on the first request.
+fn configure(
+    rocket: &Rocket<Build>,
+) -> Result<(CardstockConfig, IngestSource, Store), anyhow::Error> {
+    let config: CardstockConfig = rocket
+        .figment()
+        .extract()
+        .map_err(|err| anyhow::anyhow!("invalid configuration: {}", err))?;
+    config.validate()?;
+    let source = IngestSource::from_config(&config)?;
+
+    let store = Store::open(&config.sled_path)?;
+
+    Ok((config, source, store))
 }
 
-const CHRONICLER_BASE: &str = "https://api.sibr.dev/chronicler";
+async fn start_task(
+    config: &CardstockConfig,
+    source: &IngestSource,
+    store: &Store,
+) -> Result<(), anyhow::Error> {
+    cache_season_days(source, store, config.zeroth_season_with_idol_board).await?;
+    // boards can still be shown without the latest teams, so carry on to them
+    if let Err(err) = cache_teams(source, store).await {
+        log::error!(
+            "could not fetch teams, they will be retried on the next run: {:#}",
+            err
+        );
+    }
 
-const DAYS_TREE: &str = "games_v1";
-const INVERSE_DAYS_TREE: &str = "inverse_games_v1";
-const PLAYER_TREE: &str = "players_v1";
-const IDOLS_TREE: &str = "idols_v1";
-const TEAM_TREE: &str = "teams_v1";
+    let players = cache_idol_boards(source, store, config.keep_raw_idol_boards).await?;
 
-const ZEROTH_SEASON_WITH_IDOL_BOARD: i16 = 4;
+    let failures = cache_players(&players, source, store, config.ingest_concurrency).await;
 
-const BEGINNING_OF_TIME: &str = "2020-01-01T00:00:00Z";
-const END_OF_TIME: &str = "2099-01-01T00:00:00Z";
+    fix_necromancy(store)?;
+    build_player_index(store)?;
+    build_team_index(store)?;
+
+    if !failures.is_empty() {
+        log::error!(
+            "could not fetch {} of {} players, they will be retried on the next run:",
+            failures.len(),
+            players.len()
+        );
+        for (player, err) in failures.iter() {
+            log::error!("  {}: {:#}", player, err);
+        }
+    }
+
+    Ok(())
+}
 
-async fn start_task() -> Result<(), anyhow::Error> {
-    cache_season_days()?;
-    cache_teams()?;
+/// Caches every player, with up to `concurrency` fetches in flight at once. A player that can't be
+/// fetched doesn't stop the others, and is returned along with why.
+async fn cache_players(
+    players: &[Uuid],
+    source: &IngestSource,
+    store: &Store,
+    concurrency: usize,
+) -> Vec<(Uuid, anyhow::Error)> {
+    let mut pending = players.iter();
+    let mut in_flight = FuturesUnordered::new();
+    let mut failures = Vec::new();
+
+    loop {
+        while in_flight.len() < concurrency.max(1) {
+            match pending.next() {
+                Some(player) => in_flight.push(cache_player_and_report(player, source, store)),
+                None => break,
+            }
+        }
+
+        match in_flight.next().await {
+            Some((player, Err(err))) => {
+                log::warn!("failed to fetch player {}: {:#}", player, err);
+                failures.push((player, err));
+            }
+            Some((_, Ok(()))) => (),
+            None => break,
+        }
+    }
 
-    let idols_tree = DB.open_tree(IDOLS_TREE)?;
+    failures
+}
 
-    let contents = fs::read_to_string("data/idols.json").unwrap();
-    log::info!("read idol board data from file");
+async fn cache_player_and_report(
+    player: &Uuid,
+    source: &IngestSource,
+    store: &Store,
+) -> (Uuid, Result<(), anyhow::Error>) {
+    let result = cache_player(player, source, store).await;
+    (*player, result)
+}
 
-    let chron_idols_data: Chron2Response<idol::Idols> = serde_json::from_str(&contents).unwrap();
+/// Stores every idol board, collapsing consecutive versions with the same arrangement of players
+/// and the same noodle into the first of them. Early chronicler data has a new version whenever
+/// the fan totals changed, even if the board didn't, and the collapsed board keeps the totals from
+/// when it was posted, so they match its timestamp. With `keep_raw_boards`, every version is also
+/// kept as-is.
+///
+/// Returns every player who appeared on a board, in the order they first appeared.
+async fn cache_idol_boards(
+    source: &IngestSource,
+    store: &Store,
+    keep_raw_boards: bool,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let idol_board_versions = source.idol_board_versions().await?;
+    log::info!("read idol board data");
 
     let mut player_set = std::collections::HashSet::new();
-    let player_tree = DB.open_tree(PLAYER_TREE)?;
-
-    for idol_board_version in chron_idols_data.items.into_iter() {
-        let idol_data = match idol_board_version.data {
-            idol::Idols::IdolArray(array) => idol::IdolsClass {
-                data: idol::Data {
-                    strictly_confidential: 20,
-                },
-                idols: array.into_iter().map(|y| y.player_id).collect(),
-            },
-            idol::Idols::IdolsClass(idols_class) => idols_class,
-        };
+    let mut players = Vec::new();
+    let mut current_board: Option<(DateTime<Utc>, idol::IdolsClass)> = None;
+    let mut duplicates = 0;
+
+    for idol_board_version in idol_board_versions.into_iter() {
+        let mut idol_data = idol::IdolsClass::from(idol_board_version.data);
+        idol_data.valid_to = idol_board_version.valid_to;
 
         log::info!(
             "processed idol board data for timestamp {}",
             idol_board_version.valid_from
         );
 
+        if keep_raw_boards {
+            store.insert_raw_idol_board(idol_board_version.valid_from, &idol_data)?;
+        }
+
         for player in &idol_data.idols {
-            if player_set.contains(player) {
-                continue;
+            if player_set.insert(*player) {
+                players.push(*player);
             }
+        }
 
-            player_set.insert(player.clone());
-
-            if does_any_data_exist_in_tree_for_player(player, &player_tree) {
-                log::info!(
-                    "data exists for player {} in tree from previous run of cardstock",
-                    player
-                );
+        if let Some((_, board)) = &mut current_board {
+            if board.idols == idol_data.idols && board.data == idol_data.data {
+                board.valid_to = idol_data.valid_to;
+                duplicates += 1;
+                // a board stored before deduplication, which is now covered by the earlier one
+                store.remove_idol_board(idol_board_version.valid_from)?;
                 continue;
             }
+        }
 
-            cache_player(player, &player_tree).await?;
+        if let Some((valid_from, board)) =
+            current_board.replace((idol_board_version.valid_from, idol_data))
+        {
+            store.insert_idol_board(valid_from, &board)?;
         }
+    }
 
-        idols_tree
-            .insert(
-                idol_board_version.valid_from.to_rfc3339().as_bytes(),
-                serde_json::to_vec(&idol_data)?,
-            )
-            .expect("failed to insert idol into db");
+    if let Some((valid_from, board)) = current_board {
+        store.insert_idol_board(valid_from, &board)?;
     }
 
-    fix_necromancy(&player_tree)?;
+    log::info!("collapsed {} duplicate idol boards", duplicates);
 
-    Ok(())
+    Ok(players)
 }
 
-fn cache_season_days() -> Result<(), anyhow::Error> {
-    let days_tree = DB.open_tree(DAYS_TREE)?;
-    let inverse_days_tree = DB.open_tree(INVERSE_DAYS_TREE)?;
-
-    let contents = fs::read_to_string("data/games.json")?;
-    let games: GameData = serde_json::from_str(&contents)?;
-
-    Ok(for game in games.data.into_iter() {
-        if game.data.sim.is_none() && game.data.season > ZEROTH_SEASON_WITH_IDOL_BOARD {
+async fn cache_season_days(
+    source: &IngestSource,
+    store: &Store,
+    zeroth_season_with_idol_board: SeasonNumber,
+) -> Result<(), anyhow::Error> {
+    for game in source.games().await?.into_iter() {
+        let season = SeasonNumber::from_storage(game.data.season);
+        if game.data.sim.is_none() && season > zeroth_season_with_idol_board {
             if let Some(start_time) = game.start_time {
-                let data = game.data;
-                let key = SeasonDayKey {
-                    season: data.season.into(),
-                    day: data.day,
-                };
-                days_tree.insert(key.as_bytes(), start_time.to_rfc3339().as_bytes())?;
-                inverse_days_tree.insert(start_time.to_rfc3339().as_bytes(), key.as_bytes())?;
+                store.insert_game_day(season, game.data.day, start_time)?;
             }
         }
-    })
-}
+    }
 
-#[derive(AsBytes, FromBytes)]
-#[repr(C)]
-pub struct SeasonDayKey {
-    season: I16<BigEndian>,
-    day: u8,
+    Ok(())
 }
 
-fn cache_teams() -> Result<(), anyhow::Error> {
-    let teams_tree = DB.open_tree(TEAM_TREE)?;
+/// Stores the local team versions, then every version chronicler has that's newer than the last
+/// one fetched, a page at a time. The checkpoint is kept apart from the stored versions, since
+/// `teams.json` is newer than most of chronicler's history.
+async fn cache_teams(source: &IngestSource, store: &Store) -> Result<(), anyhow::Error> {
+    for team_data in source.local_team_versions()?.into_iter() {
+        let team = team_data.data;
+        log::info!(
+            "adding data for team {}, {} from {}",
+            team.id,
+            team.full_name,
+            team_data.valid_from
+        );
+        store.insert_team_version(team_data.valid_from, &team)?;
+    }
+
+    let mut checkpoint = store.team_ingest_checkpoint()?.unwrap_or(IngestCheckpoint {
+        valid_from: None,
+        next_page: None,
+    });
 
-    let contents = fs::read_to_string("data/teams.json")?;
-    let teams: Vec<ChronV2Versions<TeamData>> = serde_json::from_str(&contents)?;
+    let (after, mut page) = match checkpoint.next_page.take() {
+        Some(pending) => {
+            log::info!("resuming partial fetch for teams");
+            (pending.after, Some(pending.page))
+        }
+        None => (checkpoint.valid_from, None),
+    };
 
-    Ok(for team_data in teams.into_iter() {
-        let team = team_data.data;
-        log::info!("adding data for team {}, {}", team.id, team.full_name);
-        teams_tree.insert(&team.id.as_bytes(), serde_json::to_vec(&team)?)?;
-    })
-}
+    loop {
+        let team_versions = match source.team_versions(after, page.as_deref()).await? {
+            Some(team_versions) => team_versions,
+            None => break,
+        };
 
-async fn cache_player(player: &Uuid, player_tree: &Tree) -> Result<(), anyhow::Error> {
-    let url = format!(
-        "{}/v2/versions?type=Player&id={}",
-        CHRONICLER_BASE,
-        player.to_string()
-    );
-    log::info!("performing request to {}", url);
+        for version in team_versions.items.iter() {
+            store.insert_team_version(version.valid_from, &version.data)?;
 
-    let player_versions: Chron2Response<PlayerData> = CLIENT.get(url).send().await?.json().await?;
+            checkpoint.valid_from = checkpoint.valid_from.max(Some(version.valid_from));
+        }
+        log::info!("got {} team versions", team_versions.items.len());
 
-    log::info!("got data for player {}", player);
+        page = if team_versions.items.is_empty() {
+            None
+        } else {
+            team_versions.next_page
+        };
+        checkpoint.next_page = page.clone().map(|page| PendingPage { page, after });
+        store.set_team_ingest_checkpoint(&checkpoint)?;
 
-    for version in player_versions.items.into_iter() {
-        player_tree
-            .insert(
-                Key::new(*player, version.valid_from).as_bytes(),
-                serde_json::to_vec(&version.data)?,
-            )
-            .expect("failed to insert player into db");
+        if page.is_none() {
+            break;
+        }
     }
 
-    let mut next_page = player_versions.next_page;
+    Ok(())
+}
 
-    while next_page.is_some() {
-        let url = format!(
-            "{}/v2/versions?type=Player&id={}&page={}",
-            CHRONICLER_BASE,
-            player.to_string(),
-            next_page.unwrap()
-        );
+async fn cache_player(
+    player: &Uuid,
+    source: &IngestSource,
+    store: &Store,
+) -> Result<(), anyhow::Error> {
+    let mut checkpoint = match store.ingest_checkpoint(*player)? {
+        Some(checkpoint) => checkpoint,
+        // players cached before checkpoints were kept carry on from their newest stored version
+        None => IngestCheckpoint {
+            valid_from: store.latest_player_version_time(*player)?,
+            next_page: None,
+        },
+    };
 
-        let player_versions: Chron2Response<PlayerData> =
-            CLIENT.get(url).send().await?.json().await?;
+    let (after, mut page) = match checkpoint.next_page.take() {
+        Some(pending) => {
+            log::info!("resuming partial fetch for player {}", player);
+            (pending.after, Some(pending.page))
+        }
+        None => (checkpoint.valid_from, None),
+    };
+
+    if let (Some(after), None) = (after, &page) {
+        log::info!("fetching versions for player {} after {}", player, after);
+    }
 
-        next_page = player_versions.next_page;
+    loop {
+        let player_versions = source
+            .player_versions(player, after, page.as_deref())
+            .await?;
 
         log::info!("got data for player {}", player);
 
-        for version in player_versions.items.into_iter() {
+        for version in player_versions.items.iter() {
             log::info!("valid_from {}", version.valid_from);
-            player_tree
-                .insert(
-                    Key::new(*player, version.valid_from).as_bytes(),
-                    serde_json::to_vec(&version.data)?,
-                )
-                .expect("failed to insert player into db");
-        }
-    }
+            store.insert_player_version(*player, version.valid_from, &version.data)?;
 
-    Ok(())
-}
+            checkpoint.valid_from = checkpoint.valid_from.max(Some(version.valid_from));
+        }
 
-fn does_any_data_exist_in_tree_for_player(player: &Uuid, tree: &Tree) -> bool {
-    let result = tree
-        .get_lt(Key::new(*player, DateTime::parse_from_rfc3339(END_OF_TIME).unwrap()).as_bytes())
-        .unwrap();
+        page = if player_versions.items.is_empty() {
+            None
+        } else {
+            player_versions.next_page
+        };
+        checkpoint.next_page = page.clone().map(|page| PendingPage { page, after });
+        store.set_ingest_checkpoint(*player, &checkpoint)?;
 
-    match result {
-        Some((key_bytes, _)) => {
-            let player_in_key =
-                Uuid::from_slice(&Key::read_from(key_bytes.as_bytes()).unwrap().id).unwrap();
-            *player == player_in_key
+        if page.is_none() {
+            break;
         }
-        None => false,
     }
+
+    Ok(())
 }
 
-fn fix_necromancy(player_tree: &Tree) -> Result<(), anyhow::Error> {
+fn fix_necromancy(store: &Store) -> Result<(), anyhow::Error> {
     let id = Uuid::parse_str("04e14d7b-5021-4250-a3cd-932ba8e0a889")?;
 
-    let key = Key::new(id, DateTime::parse_from_rfc3339(BEGINNING_OF_TIME)?);
-
     let value = PlayerData {
         id,
         deceased: true,
@@ -224,34 +326,179 @@ fn fix_necromancy(player_tree: &Tree) -> Result<(), anyhow::Error> {
         team: Some(Uuid::parse_str("105bc3ff-1320-4e37-8ef0-8d595cb95dd0")?),
     };
 
-    player_tree.insert(key.as_bytes(), serde_json::to_vec(&value)?)?;
+    store.insert_player_version(
+        id,
+        DateTime::parse_from_rfc3339(BEGINNING_OF_TIME)?.with_timezone(&Utc),
+        &value,
+    )
+}
+
+/// Rebuilds the player index from scratch, with every name each player has had and the boards
+/// and seasons they were on.
+fn build_player_index(store: &Store) -> Result<(), anyhow::Error> {
+    let mut entries: HashMap<Uuid, PlayerIndexEntry> = HashMap::new();
+    for version in store.all_player_versions() {
+        let (_, player_data) = version?;
+
+        let entry = entries.entry(player_data.id).or_default();
+        if entry.names.last() != Some(&player_data.name) {
+            entry.names.retain(|name| *name != player_data.name);
+            entry.names.push(player_data.name);
+        }
+    }
+
+    for board in store.idol_boards() {
+        let (timestamp, board) = board?;
+        let season = store.game_day_at(timestamp)?.map(|(season, _, _)| season);
+
+        let board_size = board.idols.len();
+        for (position, player) in board.idols.into_iter().enumerate() {
+            if let Some(entry) = entries.get_mut(&player) {
+                entry.boards.push(BoardAppearance {
+                    timestamp: timestamp.into(),
+                    rank: position + 1,
+                });
+                entry.largest_board = entry.largest_board.max(board_size);
+
+                if let Some(season) = season {
+                    if !entry.seasons.contains(&season.storage()) {
+                        entry.seasons.push(season.storage());
+                    }
+                }
+            }
+        }
+    }
+
+    store.replace_player_index(entries.iter())?;
+    log::info!("indexed {} players for search", entries.len());
+
     Ok(())
 }
 
-#[derive(AsBytes, FromBytes)]
-#[repr(C)]
-struct Key {
-    id: [u8; 16],
-    valid_from: I64<BigEndian>,
+/// Rebuilds every team's page from scratch, going by the version of each player current when
+/// each board was posted, and the team's roster at the time.
+fn build_team_index(store: &Store) -> Result<(), anyhow::Error> {
+    let mut entries: HashMap<Uuid, TeamIndexEntry> = HashMap::new();
+    for board in store.idol_boards() {
+        let (timestamp, board) = board?;
+        let season = match store.game_day_at(timestamp)? {
+            Some((season, _, _)) => season,
+            // a board from before the first cached game can't be put in a season
+            None => continue,
+        };
+
+        // each team is only looked up once one of its players turns up on the board
+        let mut teams_at_board: HashMap<Uuid, Option<TeamData>> = HashMap::new();
+        for (position, player_id) in board.idols.into_iter().enumerate() {
+            let player_data = match store.player_at(player_id, timestamp) {
+                Ok(player_data) => player_data,
+                // the board page shows these as unknown players, so there's no team to count
+                Err(err) if err.downcast_ref::<LookupError>().is_some() => continue,
+                Err(err) => return Err(err),
+            };
+            let team_id = match player_data.team {
+                Some(team_id) => team_id,
+                None => continue,
+            };
+
+            let team_at_board = match teams_at_board.entry(team_id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(store.team_at(team_id, timestamp)?),
+            };
+            let roster = team_at_board
+                .as_ref()
+                .and_then(|team| team.roster_status(player_id));
+
+            entries.entry(team_id).or_default().add_appearance(
+                season,
+                player_id,
+                player_data.name,
+                position + 1,
+                roster,
+            );
+        }
+    }
+
+    store.replace_team_index(entries.iter())?;
+    log::info!("indexed {} teams", entries.len());
+
+    Ok(())
+}
+
+/// Reports what starting up would migrate in the database, without changing it or starting the
+/// server.
+const DRY_RUN_MIGRATIONS_FLAG: &str = "--dry-run-migrations";
+
+#[rocket::main]
+async fn main() {
+    if std::env::args()
+        .skip(1)
+        .any(|arg| arg == DRY_RUN_MIGRATIONS_FLAG)
+    {
+        if let Err(err) = dry_run_migrations() {
+            eprintln!("Error: {:#}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // as with `#[launch]`, dropping a launch error prints it
+    let _ = rocket().launch().await;
 }
 
-impl Key {
-    fn new<T: chrono::TimeZone>(id: Uuid, valid_from: DateTime<T>) -> Key {
-        Key {
-            id: *id.as_bytes(),
-            valid_from: valid_from.timestamp_nanos().into(),
+fn dry_run_migrations() -> Result<(), anyhow::Error> {
+    let config: CardstockConfig = CardstockConfig::figment()
+        .extract()
+        .map_err(|err| anyhow::anyhow!("invalid configuration: {}", err))?;
+    let (version, reports) = Store::dry_run_migrations(&config.sled_path)?;
+
+    match version {
+        Some(version) => println!("the database is at schema version {}", version),
+        None => println!("the database has no schema version recorded"),
+    }
+    if reports.is_empty() {
+        println!("nothing to migrate");
+    }
+    for report in reports {
+        println!(
+            "would migrate to schema version {}: {}",
+            report.version, report.description
+        );
+        if report.changes.is_empty() {
+            println!("  nothing to change");
+        }
+        for change in report.changes {
+            println!("  {}", change);
         }
     }
+
+    Ok(())
 }
 
-#[launch]
-fn rocket() -> _ {
-    rocket::build()
+fn rocket() -> Rocket<Build> {
+    rocket::custom(CardstockConfig::figment())
+        .attach(AdHoc::try_on_ignite("Configuration", |rocket| async {
+            match configure(&rocket) {
+                Ok((config, source, store)) => {
+                    Ok(rocket.manage(config).manage(source).manage(store))
+                }
+                Err(err) => {
+                    log::error!("{:#}", err);
+                    Err(rocket)
+                }
+            }
+        }))
         .mount(
             "/",
             routes![
                 routes::index::index,
                 routes::season::season,
+                routes::board::board,
+                routes::diff::board_diff,
+                routes::player::player,
+                routes::search::search,
+                routes::team::team,
+                routes::graph::player_graph,
                 routes::css,
                 routes::cardstock,
                 routes::nav_first_image,
@@ -261,13 +508,30 @@ fn rocket() -> _ {
                 routes::manifest,
             ],
         )
-        .attach(AdHoc::on_liftoff("Background tasks", |_rocket| {
+        .mount(
+            "/api/v1",
+            routes![
+                routes::api::seasons,
+                routes::api::season,
+                routes::api::board,
+                routes::api::board_diff,
+                routes::api::player,
+                routes::api::search,
+                routes::api::team,
+            ],
+        )
+        .attach(AdHoc::on_liftoff("Background tasks", |rocket| {
             Box::pin(async {
-                match start_task().await {
-                    Err(err) => {
-                        log::error!("{:#}", err);
-                    }
-                    _ => (),
+                let (config, source, store) = match (
+                    rocket.state::<CardstockConfig>(),
+                    rocket.state::<IngestSource>(),
+                    rocket.state::<Store>(),
+                ) {
+                    (Some(config), Some(source), Some(store)) => (config, source, store),
+                    _ => return,
+                };
+                if let Err(err) = start_task(config, source, store).await {
+                    log::error!("{:#}", err);
                 }
             })
         }))
@@ -288,6 +552,7 @@ struct Chron1Versions {
 #[derive(Deserialize)]
 struct Game {
     day: u8,
+    /// counted from 0, see [`SeasonNumber`]
     season: i16,
     sim: Option<String>,
 }
@@ -303,15 +568,378 @@ struct Chron2Response<T> {
 struct ChronV2Versions<T> {
     #[serde(rename = "validFrom")]
     valid_from: DateTime<Utc>,
+    #[serde(rename = "validTo")]
+    valid_to: Option<DateTime<Utc>>,
     data: T,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct TeamData {
-    pub id: Uuid,
-    #[serde(rename = "fullName")]
-    pub full_name: String,
-    #[serde(rename = "mainColor")]
-    pub colour: String,
-    pub emoji: String,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::team::RosterStatus;
+    use crate::test_support::{at, board, player, team};
+    use std::fs;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    /// a temporary directory called `name` holding `files`
+    fn data_directory(name: &str, files: &[(&str, serde_json::Value)]) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("cardstock-{}-{}", name, std::process::id()));
+        for (path, contents) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents.to_string()).unwrap();
+        }
+        directory
+    }
+
+    /// a `snapshots:` directory called `name` holding `files`
+    fn snapshot(name: &str, files: &[(&str, serde_json::Value)]) -> IngestSource {
+        IngestSource::Snapshots(data_directory(name, files))
+    }
+
+    fn remove_snapshot(source: &IngestSource) {
+        if let IngestSource::Snapshots(directory) | IngestSource::LocalFiles(directory, _) = source
+        {
+            fs::remove_dir_all(directory).unwrap();
+        }
+    }
+
+    /// a page of chronicler's `v2/versions`, each version valid until the next
+    fn versions_page(
+        next_page: Option<&str>,
+        versions: &[(&str, serde_json::Value)],
+    ) -> serde_json::Value {
+        let valid_to = versions
+            .iter()
+            .skip(1)
+            .map(|(valid_from, _)| Some(*valid_from))
+            .chain([None]);
+        let items = versions
+            .iter()
+            .zip(valid_to)
+            .map(|((valid_from, data), valid_to)| {
+                serde_json::json!({
+                    "validFrom": valid_from,
+                    "validTo": valid_to,
+                    "data": data,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({ "nextPage": next_page, "items": items })
+    }
+
+    /// A chronicler on localhost that answers each request with the next of `responses`, and
+    /// sends on the path and query of each request it gets.
+    fn mock_chronicler(responses: Vec<String>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for body in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    header.clear();
+                }
+                sender
+                    .send(request_line.split(' ').nth(1).unwrap().to_string())
+                    .unwrap();
+
+                write!(
+                    stream,
+                    concat!(
+                        "HTTP/1.1 200 OK\r\n",
+                        "Content-Type: application/json\r\n",
+                        "Content-Length: {}\r\n",
+                        "Connection: close\r\n\r\n{}",
+                    ),
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        (base, receiver)
+    }
+
+    fn stored_boards(store: &Store) -> Vec<idol::IdolsClass> {
+        store.idol_boards().map(|board| board.unwrap().1).collect()
+    }
+
+    #[test]
+    fn boards_are_only_collapsed_when_the_noodle_stays_put() {
+        let board = |noodle| {
+            serde_json::json!({
+                "idols": [Uuid::from_u128(1)],
+                "data": { "strictlyConfidential": noodle },
+            })
+        };
+        let source = snapshot(
+            "noodle",
+            &[(
+                "idols.json",
+                versions_page(
+                    None,
+                    &[
+                        ("2021-03-01T00:00:00Z", board(5)),
+                        ("2021-03-01T01:00:00Z", board(6)),
+                        ("2021-03-01T02:00:00Z", board(6)),
+                    ],
+                ),
+            )],
+        );
+        let store = Store::temporary().unwrap();
+
+        rocket::async_test(cache_idol_boards(&source, &store, false)).unwrap();
+
+        let noodles = stored_boards(&store)
+            .into_iter()
+            .map(|board| board.data.unwrap().strictly_confidential)
+            .collect::<Vec<_>>();
+        assert_eq!(noodles, [5, 6]);
+
+        remove_snapshot(&source);
+    }
+
+    #[test]
+    fn collapsed_boards_keep_the_fan_totals_they_were_posted_with() {
+        let (first, second) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let board = |first_total, second_total| {
+            serde_json::json!([
+                { "playerId": first, "total": first_total },
+                { "playerId": second, "total": second_total },
+            ])
+        };
+        let source = snapshot(
+            "totals",
+            &[(
+                "idols.json",
+                versions_page(
+                    None,
+                    &[
+                        ("2020-08-01T00:00:00Z", board(10, 5)),
+                        ("2020-08-01T01:00:00Z", board(12, 6)),
+                        ("2020-08-01T02:00:00Z", board(15, 9)),
+                    ],
+                ),
+            )],
+        );
+        let store = Store::temporary().unwrap();
+
+        rocket::async_test(cache_idol_boards(&source, &store, false)).unwrap();
+
+        let boards = stored_boards(&store);
+        assert_eq!(boards.len(), 1);
+        assert_eq!(boards[0].totals, Some(vec![Some(10), Some(5)]));
+
+        remove_snapshot(&source);
+    }
+
+    #[test]
+    fn team_fetches_carry_on_from_the_last_version_fetched() {
+        let id = Uuid::from_u128(3);
+        let version = |name| serde_json::to_value(team(id, name)).unwrap();
+        let (base, requests) = mock_chronicler(vec![
+            versions_page(
+                Some("cGFnZSAy"),
+                &[("2020-08-01T00:00:00Z", version("Early Llamas"))],
+            )
+            .to_string(),
+            versions_page(None, &[("2020-09-01T00:00:00Z", version("Later Llamas"))]).to_string(),
+            versions_page(None, &[]).to_string(),
+        ]);
+        let directory = data_directory(
+            "teams",
+            &[(
+                "teams.json",
+                serde_json::json!([{
+                    "validFrom": "2021-07-01T00:00:00Z",
+                    "validTo": null,
+                    "data": version("Saved Llamas"),
+                }]),
+            )],
+        );
+        let config: CardstockConfig = rocket::figment::Figment::from(rocket::Config::default())
+            .merge(("sled_path", "unused"))
+            .merge(("chronicler_requests_per_second", 1000))
+            .extract()
+            .unwrap();
+        let source =
+            IngestSource::LocalFiles(directory, source::Chronicler::new(&base, &config).unwrap());
+        let store = Store::temporary().unwrap();
+
+        rocket::async_test(async {
+            cache_teams(&source, &store).await?;
+            cache_teams(&source, &store).await
+        })
+        .unwrap();
+
+        let requests = requests.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            [
+                "/v2/versions?type=Team",
+                "/v2/versions?type=Team&page=cGFnZSAy",
+                "/v2/versions?type=Team&after=2020-09-01T00%3A00%3A00Z",
+            ]
+        );
+
+        let name_at = |timestamp| store.team_at(id, at(timestamp)).unwrap().unwrap().full_name;
+        assert_eq!(name_at("2020-08-15T00:00:00Z"), "Early Llamas");
+        assert_eq!(name_at("2020-10-01T00:00:00Z"), "Later Llamas");
+        assert_eq!(name_at("2021-08-01T00:00:00Z"), "Saved Llamas");
+
+        remove_snapshot(&source);
+    }
+
+    #[test]
+    fn team_index_goes_by_the_team_at_each_board() {
+        let (mover, stayer) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let (first_team, second_team) = (Uuid::from_u128(3), Uuid::from_u128(4));
+        let store = Store::temporary().unwrap();
+        store
+            .insert_game_day(
+                SeasonNumber::from_storage(5),
+                0,
+                at("2020-09-07T16:00:00Z").into(),
+            )
+            .unwrap();
+        for (id, name, team_id, valid_from) in [
+            (mover, "Mover", first_team, "2020-09-01T00:00:00Z"),
+            (mover, "Mover", second_team, "2020-09-07T17:00:00Z"),
+            (stayer, "Stayer", first_team, "2020-09-01T00:00:00Z"),
+        ] {
+            let mut player = player(id, name);
+            player.team = Some(team_id);
+            store
+                .insert_player_version(id, at(valid_from).into(), &player)
+                .unwrap();
+        }
+        let mut team = team(first_team, "Llamas");
+        team.lineup = vec![stayer];
+        store
+            .insert_team_version(at("2020-09-01T00:00:00Z").into(), &team)
+            .unwrap();
+        for posted in ["2020-09-07T16:30:00Z", "2020-09-07T17:30:00Z"] {
+            store
+                .insert_idol_board(at(posted).into(), &board(&[mover, stayer]))
+                .unwrap();
+        }
+
+        build_team_index(&store).unwrap();
+
+        let first = store.team_index(first_team).unwrap().unwrap();
+        assert_eq!(first.seasons.len(), 1);
+        assert_eq!(
+            (
+                first.seasons[0].season,
+                first.seasons[0].appearances,
+                first.seasons[0].players
+            ),
+            (5, 3, 2)
+        );
+        let players = first
+            .players
+            .iter()
+            .map(|player| {
+                (
+                    player.id,
+                    player.appearances,
+                    player.best_rank,
+                    player.roster,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            players,
+            [
+                (mover, 1, 1, None),
+                (stayer, 2, 2, Some(RosterStatus::Active))
+            ]
+        );
+
+        let second = store.team_index(second_team).unwrap().unwrap();
+        assert_eq!(second.players.len(), 1);
+        assert_eq!(second.players[0].id, mover);
+    }
+
+    #[test]
+    fn unreadable_responses_are_retried() {
+        let (base, requests) = mock_chronicler(vec![
+            r#"{"data": ["#.to_string(),
+            serde_json::json!({ "data": [] }).to_string(),
+        ]);
+        let config: CardstockConfig = rocket::figment::Figment::from(rocket::Config::default())
+            .merge(("sled_path", "unused"))
+            .merge(("chronicler_requests_per_second", 1000))
+            .extract()
+            .unwrap();
+        let source = IngestSource::Chronicler(source::Chronicler::new(&base, &config).unwrap());
+
+        let games = rocket::async_test(source.games()).unwrap();
+
+        assert!(games.is_empty());
+        assert_eq!(requests.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn cache_player_reads_a_snapshot_once() {
+        let id = Uuid::from_u128(1);
+        let version = |name| serde_json::to_value(player(id, name)).unwrap();
+        // saved with chronicler's nextPage left in
+        let source = Arc::new(snapshot(
+            "player",
+            &[(
+                &format!("players/{}.json", id),
+                versions_page(
+                    Some("c29tZSBwYWdl"),
+                    &[
+                        ("2020-08-01T00:00:00Z", version("Old Name")),
+                        ("2020-09-01T00:00:00Z", version("New Name")),
+                    ],
+                ),
+            )],
+        ));
+        let store = Arc::new(Store::temporary().unwrap());
+
+        // reading a snapshot never yields to the runtime, so a request for the same page over
+        // and over can only be caught from another thread
+        let (sender, receiver) = mpsc::channel();
+        let (thread_source, thread_store) = (source.clone(), store.clone());
+        thread::spawn(move || {
+            let result = rocket::async_test(async {
+                cache_player(&id, &thread_source, &thread_store).await?;
+                cache_player(&id, &thread_source, &thread_store).await
+            });
+            sender.send(result).unwrap();
+        });
+        receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("cache_player kept asking for the next page")
+            .unwrap();
+
+        let versions = store.player_versions(id).unwrap();
+        assert_eq!(
+            versions
+                .iter()
+                .map(|(_, player)| player.name.as_str())
+                .collect::<Vec<_>>(),
+            ["Old Name", "New Name"]
+        );
+
+        let checkpoint = store.ingest_checkpoint(id).unwrap().unwrap();
+        assert!(checkpoint.next_page.is_none());
+        assert_eq!(checkpoint.valid_from, Some(versions[1].0));
+
+        remove_snapshot(&source);
+    }
 }
@@ -0,0 +1,64 @@
+//! JSON versions of the HTML pages, mounted under `/api/v1`. Each route uses the same loader as
+//! the page it mirrors, so the two can't drift apart.
+
+use crate::entities::season::SeasonNumber;
+use crate::routes::board::{load_board_page, BoardPage};
+use crate::routes::diff::{load_board_diff, BoardDiffPage};
+use crate::routes::index::{load_index, IndexPage};
+use crate::routes::player::{load_player_page, PlayerPage};
+use crate::routes::search::{load_search, SearchPage};
+use crate::routes::season::{load_season, SeasonPage, SeasonWindow};
+use crate::routes::team::{load_team_page, TeamPage};
+use crate::routes::{ResponseResult, TimestampParam};
+
+use crate::store::Store;
+
+use rocket::serde::json::Json;
+use rocket::{get, State};
+use uuid::Uuid;
+
+#[get("/seasons")]
+pub fn seasons() -> ResponseResult<Json<IndexPage>> {
+    Ok(Json(load_index()?))
+}
+
+#[get("/season/<season>?<diff>&<window..>")]
+pub fn season(
+    season: SeasonNumber,
+    diff: Option<bool>,
+    window: SeasonWindow,
+    store: &State<Store>,
+) -> ResponseResult<Option<Json<SeasonPage>>> {
+    Ok(load_season(season, &window, diff.unwrap_or(false), store)?.map(Json))
+}
+
+#[get("/board/<timestamp>")]
+pub fn board(
+    timestamp: TimestampParam,
+    store: &State<Store>,
+) -> ResponseResult<Option<Json<BoardPage>>> {
+    Ok(load_board_page(timestamp.0, store)?.map(Json))
+}
+
+#[get("/board/<timestamp>/diff")]
+pub fn board_diff(
+    timestamp: TimestampParam,
+    store: &State<Store>,
+) -> ResponseResult<Option<Json<BoardDiffPage>>> {
+    Ok(load_board_diff(timestamp.0, store)?.map(Json))
+}
+
+#[get("/player/<player_id>")]
+pub fn player(player_id: Uuid, store: &State<Store>) -> ResponseResult<Option<Json<PlayerPage>>> {
+    Ok(load_player_page(player_id, store)?.map(Json))
+}
+
+#[get("/search?<q>")]
+pub fn search(q: Option<String>, store: &State<Store>) -> ResponseResult<Json<SearchPage>> {
+    Ok(Json(load_search(q.as_deref().unwrap_or(""), store)?))
+}
+
+#[get("/team/<team_id>")]
+pub fn team(team_id: Uuid, store: &State<Store>) -> ResponseResult<Option<Json<TeamPage>>> {
+    Ok(load_team_page(team_id, store)?.map(Json))
+}
@@ -0,0 +1,87 @@
+use crate::routes::{IdolBoard, ResponseResult, TimestampParam};
+use crate::store::{LookupError, Store};
+
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, FixedOffset};
+use rocket::response::content::RawHtml;
+use rocket::{get, State};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::convert_idol_board;
+use super::get_annotations_for_season;
+use super::get_timestamp_for_board;
+use super::set_board_gaps;
+
+#[get("/board/<timestamp>")]
+pub fn board(
+    timestamp: TimestampParam,
+    store: &State<Store>,
+) -> ResponseResult<Option<RawHtml<String>>> {
+    Ok(match load_board_page(timestamp.0, store)? {
+        Some(board_page) => Some(RawHtml(board_page.render().map_err(anyhow::Error::from)?)),
+        None => None,
+    })
+}
+
+/// The board that was showing at `timestamp`, i.e. the latest one posted at or before it, with
+/// the players' names and teams as they were at `timestamp` rather than when the board was posted.
+pub fn load_board_page(
+    timestamp: DateTime<FixedOffset>,
+    store: &Store,
+) -> Result<Option<BoardPage>> {
+    let (board_timestamp, idols) = match store.idol_board_at(timestamp)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+
+    let board_time = get_timestamp_for_board(board_timestamp, store)?
+        .ok_or(LookupError::NoGameDayYet(board_timestamp))?;
+    let mut board = convert_idol_board(board_time, idols, timestamp, store, &HashMap::new())?;
+    board.apply_annotations(&get_annotations_for_season(board.timestamp.season)?);
+
+    let previous = store
+        .idol_board_before(board_timestamp)?
+        .map(|(timestamp, _)| timestamp);
+    let next = store
+        .idol_board_after(board_timestamp)?
+        .map(|(timestamp, _)| timestamp);
+    let neighbour = |timestamp: Option<DateTime<FixedOffset>>| match timestamp {
+        Some(timestamp) => get_timestamp_for_board(timestamp, store),
+        None => Ok(None),
+    };
+    set_board_gaps(
+        std::slice::from_mut(&mut board),
+        neighbour(previous)?,
+        neighbour(next)?,
+        store,
+    );
+
+    let link = |timestamp: Option<DateTime<FixedOffset>>| {
+        timestamp.map(|timestamp| TimestampParam(timestamp).to_url_string())
+    };
+    Ok(Some(BoardPage {
+        requested: timestamp,
+        previous: link(previous),
+        next: link(next),
+        board,
+    }))
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "board.html")]
+pub struct BoardPage {
+    requested: DateTime<FixedOffset>,
+    board: IdolBoard,
+    /// timestamps of the neighbouring boards, formatted for `/board/<timestamp>`
+    previous: Option<String>,
+    next: Option<String>,
+}
+
+impl BoardPage {
+    /// the board's own timestamp, formatted for `/board/<timestamp>/diff`
+    fn posted(&self) -> String {
+        TimestampParam(self.board.timestamp.timestamp).to_url_string()
+    }
+}
@@ -0,0 +1,232 @@
+use crate::routes::{IdolBoard, ResponseResult, Timestamp, TimestampParam};
+use crate::store::{LookupError, Store};
+
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, FixedOffset};
+use rocket::response::content::RawHtml;
+use rocket::{get, State};
+use serde::Serialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::convert_idol_board;
+use super::get_timestamp_for_board;
+
+/// a move of at least this many places between two boards is called out separately
+const LARGE_JUMP: i64 = 5;
+
+#[get("/board/<timestamp>/diff")]
+pub fn board_diff(
+    timestamp: TimestampParam,
+    store: &State<Store>,
+) -> ResponseResult<Option<RawHtml<String>>> {
+    Ok(match load_board_diff(timestamp.0, store)? {
+        Some(diff_page) => Some(RawHtml(diff_page.render().map_err(anyhow::Error::from)?)),
+        None => None,
+    })
+}
+
+/// How the board that was showing at `timestamp` differs from the one before it.
+pub fn load_board_diff(
+    timestamp: DateTime<FixedOffset>,
+    store: &Store,
+) -> Result<Option<BoardDiffPage>> {
+    let current = match store.idol_board_at(timestamp)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    let previous = match store.idol_board_before(current.0)? {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+
+    let convert = |(board_timestamp, idols)| -> Result<IdolBoard> {
+        let board_time = get_timestamp_for_board(board_timestamp, store)?
+            .ok_or(LookupError::NoGameDayYet(board_timestamp))?;
+        convert_idol_board(board_time, idols, board_timestamp, store, &HashMap::new())
+    };
+    let previous = convert(previous)?;
+    let current = convert(current)?;
+
+    Ok(Some(BoardDiffPage {
+        diff: BoardDiff::between(&previous, &current),
+        from: previous.timestamp,
+        to: current.timestamp,
+    }))
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "board_diff.html")]
+pub struct BoardDiffPage {
+    from: Timestamp,
+    to: Timestamp,
+    diff: BoardDiff,
+}
+
+/// The movement between two consecutive boards, in the order of the later board followed by the
+/// players who dropped off it.
+#[derive(Serialize)]
+pub struct BoardDiff {
+    pub(super) entries: Vec<DiffEntry>,
+}
+
+#[derive(Serialize)]
+pub struct DiffEntry {
+    pub(super) id: Uuid,
+    pub(super) name: String,
+    /// `None` if the player dropped off the board
+    pub(super) rank: Option<usize>,
+    /// `None` if the player is new to the board
+    previous_rank: Option<usize>,
+}
+
+impl BoardDiff {
+    pub fn between(previous: &IdolBoard, current: &IdolBoard) -> BoardDiff {
+        let rank_on = |board: &IdolBoard, id: Uuid| {
+            board
+                .players
+                .iter()
+                .position(|player| player.id == id)
+                .map(|position| position + 1)
+        };
+
+        let moved = current
+            .players
+            .iter()
+            .enumerate()
+            .map(|(position, player)| DiffEntry {
+                id: player.id,
+                name: player.name.clone(),
+                rank: Some(position + 1),
+                previous_rank: rank_on(previous, player.id),
+            });
+        let dropped = previous
+            .players
+            .iter()
+            .enumerate()
+            .filter(|(_, player)| rank_on(current, player.id).is_none())
+            .map(|(position, player)| DiffEntry {
+                id: player.id,
+                name: player.name.clone(),
+                rank: None,
+                previous_rank: Some(position + 1),
+            });
+
+        BoardDiff {
+            entries: moved.chain(dropped).collect(),
+        }
+    }
+
+    pub(super) fn is_unchanged(&self) -> bool {
+        self.entries.iter().all(|entry| entry.change() == Some(0))
+    }
+}
+
+impl DiffEntry {
+    /// places moved up the board, so negative if the player fell
+    pub(super) fn change(&self) -> Option<i64> {
+        match (self.previous_rank, self.rank) {
+            (Some(previous_rank), Some(rank)) => Some(previous_rank as i64 - rank as i64),
+            _ => None,
+        }
+    }
+
+    pub(super) fn is_new(&self) -> bool {
+        self.previous_rank.is_none()
+    }
+
+    pub(super) fn is_dropped(&self) -> bool {
+        self.rank.is_none()
+    }
+
+    pub(super) fn is_large_jump(&self) -> bool {
+        self.change()
+            .is_some_and(|change| change.abs() >= LARGE_JUMP)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::player::PlayerDisplayable;
+    use crate::entities::season::SeasonNumber;
+    use crate::test_support::at;
+
+    fn idol_board(idols: &[u128]) -> IdolBoard {
+        IdolBoard {
+            timestamp: Timestamp {
+                timestamp: at("2020-09-07T16:30:00Z"),
+                season: SeasonNumber::from_storage(5),
+                day: 0,
+                time_since_game_start: 30.0,
+            },
+            players: idols
+                .iter()
+                .map(|id| PlayerDisplayable::unknown(Uuid::from_u128(*id)))
+                .collect(),
+            noodle: None,
+            annotations: Vec::new(),
+            diff: None,
+            markers: Vec::new(),
+            previous_gap: None,
+            next_gap: None,
+        }
+    }
+
+    fn changes(diff: &BoardDiff) -> Vec<(u128, Option<i64>)> {
+        diff.entries
+            .iter()
+            .map(|entry| (entry.id.as_u128(), entry.change()))
+            .collect()
+    }
+
+    #[test]
+    fn players_who_dropped_off_come_after_the_later_board() {
+        let diff = BoardDiff::between(&idol_board(&[1, 2, 3]), &idol_board(&[2, 4, 1]));
+
+        assert_eq!(
+            changes(&diff),
+            [(2, Some(1)), (4, None), (1, Some(-2)), (3, None)]
+        );
+        let new = diff
+            .entries
+            .iter()
+            .map(DiffEntry::is_new)
+            .collect::<Vec<_>>();
+        assert_eq!(new, [false, true, false, false]);
+        let dropped = diff
+            .entries
+            .iter()
+            .map(DiffEntry::is_dropped)
+            .collect::<Vec<_>>();
+        assert_eq!(dropped, [false, false, false, true]);
+        assert_eq!(diff.entries[3].previous_rank, Some(3));
+        assert!(!diff.is_unchanged());
+    }
+
+    #[test]
+    fn the_same_board_twice_is_unchanged() {
+        let diff = BoardDiff::between(&idol_board(&[1, 2, 3]), &idol_board(&[1, 2, 3]));
+
+        assert!(diff.is_unchanged());
+    }
+
+    #[test]
+    fn large_jumps_start_at_five_places_either_way() {
+        let large_jumps = |previous, current| {
+            BoardDiff::between(&idol_board(previous), &idol_board(current))
+                .entries
+                .iter()
+                .filter(|entry| entry.is_large_jump())
+                .map(|entry| entry.id.as_u128())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(large_jumps(&[1, 2, 3, 4, 5, 6], &[6, 1, 2, 3, 4, 5]), [6]);
+        assert_eq!(large_jumps(&[1, 2, 3, 4, 5, 6], &[2, 3, 4, 5, 6, 1]), [1]);
+        assert!(large_jumps(&[1, 2, 3, 4, 5, 6], &[5, 1, 2, 3, 4, 6]).is_empty());
+        // coming onto the board isn't a jump, however high up
+        assert!(large_jumps(&[1, 2, 3, 4, 5, 6], &[7, 1, 2, 3, 4, 5]).is_empty());
+    }
+}
@@ -0,0 +1,40 @@
+use crate::store::LookupError;
+
+use rocket::http::Status;
+use rocket::response::{self, Responder};
+use rocket::Request;
+
+/// what a page gets for asking for something that isn't cached
+fn lookup_status(error: &LookupError) -> Status {
+    match error {
+        LookupError::NoGameDayYet(_) | LookupError::UnknownPlayer(_) => Status::NotFound,
+    }
+}
+
+/// The error side of [`super::ResponseResult`]: a [`LookupError`] becomes its status code, and
+/// anything else is a 500.
+#[derive(Debug)]
+pub struct ResponseError(anyhow::Error);
+
+impl From<anyhow::Error> for ResponseError {
+    fn from(err: anyhow::Error) -> Self {
+        ResponseError(err)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ResponseError {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        let status = match self.0.downcast_ref::<LookupError>() {
+            Some(lookup_error) => lookup_status(lookup_error),
+            None => Status::InternalServerError,
+        };
+
+        if status == Status::InternalServerError {
+            log::error!("{:#}", self.0);
+        } else {
+            log::info!("{:#}", self.0);
+        }
+
+        Err(status)
+    }
+}
@@ -0,0 +1,302 @@
+use crate::entities::season::SeasonNumber;
+use crate::routes::ResponseResult;
+use crate::store::Store;
+
+use anyhow::Result;
+use askama::Template;
+use chrono::Duration;
+use rocket::http::ContentType;
+use rocket::{get, State};
+use uuid::Uuid;
+
+use super::get_displayable_data_for_team;
+
+const GRAPH_WIDTH: f32 = 560.0;
+const GRAPH_HEIGHT: f32 = 240.0;
+const GRAPH_PADDING_LEFT: f32 = 32.0;
+const GRAPH_PADDING_RIGHT: f32 = 12.0;
+const GRAPH_PADDING_TOP: f32 = 12.0;
+const GRAPH_PADDING_BOTTOM: f32 = 24.0;
+
+#[get("/player/<player_id>/graph.svg")]
+pub fn player_graph(
+    player_id: Uuid,
+    store: &State<Store>,
+) -> ResponseResult<Option<(ContentType, String)>> {
+    Ok(match load_player_graph(player_id, store)? {
+        Some(graph) => Some((
+            ContentType::SVG,
+            graph.render().map_err(anyhow::Error::from)?,
+        )),
+        None => None,
+    })
+}
+
+fn load_player_graph(player_id: Uuid, store: &Store) -> Result<Option<PlayerGraph>> {
+    let player_data = store.player_versions(player_id)?;
+    if player_data.is_empty() {
+        return Ok(None);
+    }
+
+    // every game day in games_v1, in order, so the x axis is measured in days rather than
+    // wall-clock time and the gaps between seasons don't swallow the graph
+    let game_days = store.game_days()?;
+
+    let day_position = |timestamp| -> Result<Option<f32>> {
+        let (season, day, time_since_game_start) = match store.game_day_at(timestamp)? {
+            Some(day_and_time) => day_and_time,
+            // boards from before the first cached game can't be placed on the axis
+            None => return Ok(None),
+        };
+        Ok(game_days
+            .binary_search(&(season, day))
+            .ok()
+            .map(|index| index as f32 + (time_since_game_start / 60_f32).clamp(0_f32, 1_f32)))
+    };
+
+    let entry = store.player_index_entry(player_id)?.unwrap_or_default();
+    let lowest_rank = entry.largest_board.max(1);
+    let board_times = match (entry.boards.first(), entry.boards.last()) {
+        // only the keys, to find the boards the player wasn't on
+        (Some(first), Some(last)) => store.idol_board_times(
+            first.timestamp.into(),
+            (last.timestamp + Duration::nanoseconds(1)).into(),
+        )?,
+        _ => Vec::new(),
+    };
+
+    // (day position, rank), split wherever the player dropped off the board
+    let mut segments: Vec<Vec<(f32, usize)>> = vec![Vec::new()];
+    let mut first_board = None;
+    let mut last_board = None;
+    let mut appearances = entry.boards.iter().peekable();
+    for timestamp in board_times {
+        // boards collapsed away since the index was built
+        while appearances
+            .next_if(|appearance| appearance.timestamp < timestamp)
+            .is_some()
+        {}
+
+        let rank = match appearances.next_if(|appearance| appearance.timestamp == timestamp) {
+            Some(appearance) => appearance.rank,
+            None => {
+                if !segments.last().unwrap().is_empty() {
+                    segments.push(Vec::new());
+                }
+                continue;
+            }
+        };
+
+        if let Some(position) = day_position(timestamp)? {
+            segments.last_mut().unwrap().push((position, rank));
+            first_board.get_or_insert(timestamp);
+            last_board = Some(timestamp);
+        }
+    }
+    segments.retain(|segment| !segment.is_empty());
+
+    let (first_board, last_board) = match (first_board, last_board) {
+        (Some(first_board), Some(last_board)) => (first_board, last_board),
+        _ => return Ok(None),
+    };
+
+    let points = segments.iter().flatten();
+    let first_day = points.clone().map(|(x, _)| *x).fold(f32::MAX, f32::min);
+    let last_day = points.map(|(x, _)| *x).fold(f32::MIN, f32::max);
+    let scale = Scale {
+        first_day,
+        day_span: (last_day - first_day).max(1_f32),
+        lowest_rank,
+    };
+
+    let mut markers = Vec::new();
+    for (previous, (valid_from, current)) in player_data
+        .iter()
+        .map(|(_, data)| data)
+        .zip(player_data.iter().skip(1))
+    {
+        let valid_from = valid_from.with_timezone(&first_board.timezone());
+        if valid_from < first_board || valid_from > last_board {
+            continue;
+        }
+
+        let label = if current.deceased && !previous.deceased {
+            Some(("Deceased".to_string(), "#000000".to_string()))
+        } else if current.team != previous.team {
+            let team = get_displayable_data_for_team(current.team, valid_from, store)?;
+            Some((format!("Joined the {}", team.name), team.colour))
+        } else {
+            None
+        };
+
+        if let Some((label, colour)) = label {
+            if let Some(position) = day_position(valid_from)? {
+                // pin the marker to the rank held on the most recent board before the change
+                let rank = segments
+                    .iter()
+                    .flatten()
+                    .take_while(|(x, _)| *x <= position)
+                    .last()
+                    .or_else(|| segments[0].first())
+                    .map(|(_, rank)| *rank)
+                    .unwrap();
+                markers.push(GraphMarker {
+                    x: scale.x(position),
+                    y: scale.y(rank),
+                    label,
+                    colour,
+                });
+            }
+        }
+    }
+
+    let mut seasons = Vec::new();
+    for (index, (season, day)) in game_days.iter().enumerate() {
+        let position = index as f32;
+        if *day == 0 && position >= first_day && position <= last_day {
+            seasons.push((scale.x(position), *season));
+        }
+    }
+
+    Ok(Some(PlayerGraph {
+        width: GRAPH_WIDTH,
+        height: GRAPH_HEIGHT,
+        left: GRAPH_PADDING_LEFT,
+        right: GRAPH_WIDTH - GRAPH_PADDING_RIGHT,
+        top: GRAPH_PADDING_TOP,
+        bottom: GRAPH_HEIGHT - GRAPH_PADDING_BOTTOM,
+        ranks: [1, lowest_rank]
+            .into_iter()
+            .map(|rank| (scale.y(rank), rank))
+            .collect(),
+        seasons,
+        paths: segments
+            .iter()
+            .map(|segment| {
+                // a lone board still needs a zero-length line for the round cap to draw a dot
+                let segment = match segment.as_slice() {
+                    [point] => vec![*point, *point],
+                    _ => segment.clone(),
+                };
+                segment
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (x, rank))| {
+                        format!(
+                            "{} {:.1} {:.1}",
+                            if index == 0 { "M" } else { "L" },
+                            scale.x(*x),
+                            scale.y(*rank)
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect(),
+        markers,
+    }))
+}
+
+struct Scale {
+    first_day: f32,
+    day_span: f32,
+    lowest_rank: usize,
+}
+
+impl Scale {
+    fn x(&self, position: f32) -> f32 {
+        GRAPH_PADDING_LEFT
+            + (position - self.first_day) / self.day_span
+                * (GRAPH_WIDTH - GRAPH_PADDING_LEFT - GRAPH_PADDING_RIGHT)
+    }
+
+    fn y(&self, rank: usize) -> f32 {
+        let rank_span = (self.lowest_rank.max(2) - 1) as f32;
+        GRAPH_PADDING_TOP
+            + (rank - 1) as f32 / rank_span
+                * (GRAPH_HEIGHT - GRAPH_PADDING_TOP - GRAPH_PADDING_BOTTOM)
+    }
+}
+
+struct GraphMarker {
+    x: f32,
+    y: f32,
+    label: String,
+    colour: String,
+}
+
+#[derive(Template)]
+#[template(path = "player_graph.svg", escape = "html")]
+struct PlayerGraph {
+    width: f32,
+    height: f32,
+    left: f32,
+    right: f32,
+    top: f32,
+    bottom: f32,
+    ranks: Vec<(f32, usize)>,
+    seasons: Vec<(f32, SeasonNumber)>,
+    paths: Vec<String>,
+    markers: Vec<GraphMarker>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::player::{BoardAppearance, PlayerIndexEntry};
+    use crate::test_support::{at, board, player};
+
+    #[test]
+    fn the_line_breaks_where_the_player_dropped_off() {
+        let store = Store::temporary().unwrap();
+        let (climber, other) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        store
+            .insert_game_day(
+                SeasonNumber::from_storage(5),
+                0,
+                at("2020-09-07T16:00:00Z").into(),
+            )
+            .unwrap();
+        store
+            .insert_player_version(
+                climber,
+                at("2020-09-01T00:00:00Z").into(),
+                &player(climber, "Climber"),
+            )
+            .unwrap();
+
+        let boards = [
+            ("2020-09-07T16:10:00Z", vec![climber, other]),
+            ("2020-09-07T16:20:00Z", vec![other, climber]),
+            ("2020-09-07T16:30:00Z", vec![other]),
+            ("2020-09-07T16:40:00Z", vec![climber]),
+        ];
+        let mut entry = PlayerIndexEntry::default();
+        for (posted, idols) in &boards {
+            store
+                .insert_idol_board(at(posted).into(), &board(idols))
+                .unwrap();
+            if let Some(position) = idols.iter().position(|id| *id == climber) {
+                entry.boards.push(BoardAppearance {
+                    timestamp: at(posted).into(),
+                    rank: position + 1,
+                });
+            }
+        }
+        entry.largest_board = 2;
+        store
+            .replace_player_index([(&climber, &entry)].into_iter())
+            .unwrap();
+
+        let graph = load_player_graph(climber, &store).unwrap().unwrap();
+        assert_eq!(graph.paths.len(), 2);
+        assert_eq!(
+            graph
+                .ranks
+                .iter()
+                .map(|(_, rank)| *rank)
+                .collect::<Vec<_>>(),
+            [1, 2]
+        );
+    }
+}
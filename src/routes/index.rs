@@ -1,39 +1,33 @@
-use crate::routes::routes::asset;
+use crate::entities::season::Era;
 use crate::routes::ResponseResult;
 use askama::Template;
 use rocket::get;
 use rocket::response::content::RawHtml;
-use serde::Deserialize;
+use serde::Serialize;
+use std::cmp::Reverse;
+
+use super::get_eras;
 
 #[get("/")]
 pub fn index() -> ResponseResult<RawHtml<String>> {
-    let mut eras = serde_json::from_str::<Vec<Era>>(asset!("/data/elections.json"))
-	.map_err(anyhow::Error::from)?;
-
-    eras.iter_mut().for_each(|era| era.seasons.sort_by(|a, b| b.index.cmp(&a.index)));
-    eras.sort_by(|a, b| b.seasons[0].index.cmp(&a.seasons[0].index));
-
-    let html_content = IndexPage { eras }.render().map_err(anyhow::Error::from)?;
+    let html_content = load_index()?.render().map_err(anyhow::Error::from)?;
 
     Ok(RawHtml(html_content))
 }
 
-#[derive(Deserialize)]
-struct Season {
-    index: i8,
-    start_date: String,
-    end_date: String,
-    election_offset: Option<String>,
-}
+/// every era and season, most recent first
+pub fn load_index() -> Result<IndexPage, anyhow::Error> {
+    let mut eras = get_eras()?;
+
+    eras.iter_mut()
+        .for_each(|era| era.seasons.sort_by_key(|season| Reverse(season.index)));
+    eras.sort_by(|a, b| b.seasons[0].index.cmp(&a.seasons[0].index));
 
-#[derive(Deserialize)]
-struct Era {
-    name: String,
-    seasons: Vec<Season>,
+    Ok(IndexPage { eras })
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "index.html")]
-struct IndexPage {
+pub struct IndexPage {
     eras: Vec<Era>,
 }
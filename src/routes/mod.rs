@@ -1,155 +1,412 @@
+pub mod api;
+pub mod board;
+pub mod diff;
+pub mod error;
+pub mod graph;
 pub mod index;
 pub mod player;
+pub mod search;
 pub mod season;
+pub mod team;
 
-use crate::entities::player::{PlayerData, PlayerDisplayable};
+use crate::entities::player::{Fans, PlayerDisplayable};
+use crate::entities::season::Annotation;
+use crate::entities::season::{Era, SeasonNumber};
 use crate::entities::team::TeamDisplayable;
-use crate::idol::Idols;
-use crate::TeamData;
-use crate::{Key, SeasonDayKey, BEGINNING_OF_TIME, DAYS_TREE, DB, END_OF_TIME};
-use chrono::{DateTime, FixedOffset};
-use rocket::response::Debug;
+use crate::idol::IdolsClass;
+use crate::routes::diff::BoardDiff;
+use crate::routes::error::ResponseError;
+use crate::routes::season::SeasonMarker;
+use crate::store::{LookupError, Store};
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use rocket::form::{self, FromFormField, ValueField};
+use rocket::request::FromParam;
 use rocket::{get, http::ContentType};
-use sled::Tree;
+use serde::Serialize;
+use std::collections::HashMap;
 use uuid::Uuid;
-use zerocopy::{AsBytes, FromBytes};
 
-pub type ResponseResult<T> = std::result::Result<T, Debug<anyhow::Error>>;
+pub type ResponseResult<T> = std::result::Result<T, ResponseError>;
 
+#[derive(Serialize)]
 pub struct Timestamp {
     timestamp: DateTime<FixedOffset>,
+    season: SeasonNumber,
     day: u8,
     time_since_game_start: f32, // not sure about units for this but its blaseball so float is probably correct
 }
 
-pub fn convert_db_contents_into_format_for_page(
-    database_contents: sled::Iter,
-    player_tree: Tree,
-    team_tree: Tree,
-    inverse_days_tree: &Tree,
-    limit: Option<u16>,
-) -> Result<Vec<(Timestamp, Vec<PlayerDisplayable>)>, anyhow::Error> {
-    let idol_boards = database_contents
-        .map(|x| {
-            let result = x.unwrap();
-            let timestamp =
-                DateTime::parse_from_rfc3339(std::str::from_utf8(result.0.as_bytes()).unwrap())
-                    .unwrap();
-
-            let (day, time_since_game_start) = get_day_and_time_since_game_start(timestamp, inverse_days_tree)?;
-
-            let idols: Idols = serde_json::from_slice(result.1.as_bytes()).unwrap();
-            let idol_data = (match idols {
-                Idols::IdolArray(array) => {
-                    let player_ids: Vec<Uuid> = array.into_iter().map(|y| y.player_id).collect();
-                    player_ids
+#[derive(Serialize)]
+pub struct IdolBoard {
+    timestamp: Timestamp,
+    players: Vec<PlayerDisplayable>,
+    /// `data.strictlyConfidential`, which is where the noodle sits on the board
+    noodle: Option<i64>,
+    annotations: Vec<RankAnnotation>,
+    /// movement since the previous board, when asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<BoardDiff>,
+    /// the parts of the season that this is the first board of
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    markers: Vec<SeasonMarker>,
+    /// how long before this board the previous one was posted, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previous_gap: Option<BoardGap>,
+    /// how long after this board the next one was posted, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_gap: Option<BoardGap>,
+}
+
+/// How far apart two boards were posted.
+#[derive(Clone, Serialize)]
+pub struct BoardGap {
+    seconds: i64,
+    /// how many game days started in between, which crosses into the next season if need be
+    game_days: usize,
+}
+
+impl BoardGap {
+    fn between(earlier: &Timestamp, later: &Timestamp, store: &Store) -> BoardGap {
+        BoardGap {
+            seconds: (later.timestamp - earlier.timestamp).num_seconds(),
+            game_days: store
+                .game_days_between((earlier.season, earlier.day), (later.season, later.day)),
+        }
+    }
+
+    /// e.g. `35m`, `2h 5m` or `3d 4h, 2 game days`
+    fn label(&self) -> String {
+        let minutes = self.seconds / 60;
+        let time = match (minutes / (60 * 24), minutes / 60 % 24, minutes % 60) {
+            (0, 0, minutes) => format!("{}m", minutes),
+            (0, hours, 0) => format!("{}h", hours),
+            (0, hours, minutes) => format!("{}h {}m", hours, minutes),
+            (days, 0, _) => format!("{}d", days),
+            (days, hours, _) => format!("{}d {}h", days, hours),
+        };
+
+        match self.game_days {
+            0 => time,
+            1 => format!("{}, 1 game day", time),
+            game_days => format!("{}, {} game days", time, game_days),
+        }
+    }
+}
+
+/// Fills in the gaps between each of `boards`, which must be consecutive, and between the first
+/// and last of them and the boards either side, if those are known.
+fn set_board_gaps(
+    boards: &mut [IdolBoard],
+    previous: Option<Timestamp>,
+    next: Option<Timestamp>,
+    store: &Store,
+) {
+    for index in 1..boards.len() {
+        let gap = BoardGap::between(
+            &boards[index - 1].timestamp,
+            &boards[index].timestamp,
+            store,
+        );
+        boards[index - 1].next_gap = Some(gap.clone());
+        boards[index].previous_gap = Some(gap);
+    }
+
+    if let (Some(previous), Some(first)) = (previous, boards.first_mut()) {
+        first.previous_gap = Some(BoardGap::between(&previous, &first.timestamp, store));
+    }
+    if let (Some(next), Some(last)) = (next, boards.last_mut()) {
+        last.next_gap = Some(BoardGap::between(&last.timestamp, &next, store));
+    }
+}
+
+/// the day and time of a board posted at `timestamp`, or `None` if it was posted before the
+/// first cached game day
+fn get_timestamp_for_board(
+    timestamp: DateTime<FixedOffset>,
+    store: &Store,
+) -> Result<Option<Timestamp>, anyhow::Error> {
+    Ok(store
+        .game_day_at(timestamp)?
+        .map(|(season, day, time_since_game_start)| Timestamp {
+            timestamp,
+            season,
+            day,
+            time_since_game_start,
+        }))
+}
+
+/// An annotation from `data/elections.json`, resolved to a position on a particular board.
+#[derive(Serialize)]
+pub struct RankAnnotation {
+    rank: usize,
+    kind: RankAnnotationKind,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RankAnnotationKind {
+    RedLine,
+    Noodle,
+    Feedback,
+    Weather,
+}
+
+impl RankAnnotationKind {
+    /// drawn as a line under the player at `rank`, rather than an icon next to them
+    fn is_line(&self) -> bool {
+        matches!(
+            self,
+            RankAnnotationKind::RedLine | RankAnnotationKind::Noodle
+        )
+    }
+
+    fn class_name(&self) -> &'static str {
+        match self {
+            RankAnnotationKind::RedLine => "red-line",
+            RankAnnotationKind::Noodle => "noodle",
+            RankAnnotationKind::Feedback => "feedback",
+            RankAnnotationKind::Weather => "weather",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            RankAnnotationKind::RedLine => "Red line",
+            RankAnnotationKind::Noodle => "The noodle",
+            RankAnnotationKind::Feedback => "Feedback",
+            RankAnnotationKind::Weather => "Weather",
+        }
+    }
+}
+
+impl IdolBoard {
+    pub fn apply_annotations(&mut self, annotations: &[Annotation]) {
+        for annotation in annotations {
+            match annotation {
+                Annotation::RedLine { after_rank } => self.annotations.push(RankAnnotation {
+                    rank: *after_rank,
+                    kind: RankAnnotationKind::RedLine,
+                }),
+                Annotation::Noodle => {
+                    if let Some(noodle) = self.noodle {
+                        self.annotations.push(RankAnnotation {
+                            rank: noodle as usize,
+                            kind: RankAnnotationKind::Noodle,
+                        })
+                    }
                 }
-                Idols::IdolsClass(idols_class) => idols_class.idols,
-            })
-            .into_iter()
-            .map(|player_id| {
-                get_displayable_data_for_player(player_id, timestamp, &player_tree, &team_tree)
-                    .unwrap()
-            })
+                Annotation::Feedback { ranks } => {
+                    self.annotations
+                        .extend(ranks.iter().map(|rank| RankAnnotation {
+                            rank: *rank,
+                            kind: RankAnnotationKind::Feedback,
+                        }))
+                }
+                Annotation::Weather { ranks } => {
+                    self.annotations
+                        .extend(ranks.iter().map(|rank| RankAnnotation {
+                            rank: *rank,
+                            kind: RankAnnotationKind::Weather,
+                        }))
+                }
+            }
+        }
+    }
+}
+
+pub fn get_eras() -> Result<Vec<Era>, anyhow::Error> {
+    Ok(serde_json::from_str(assets::asset!(
+        "/data/elections.json"
+    ))?)
+}
+
+pub fn convert_db_contents_into_format_for_page(
+    database_contents: impl Iterator<Item = Result<(DateTime<FixedOffset>, IdolsClass), anyhow::Error>>,
+    store: &Store,
+) -> Result<Vec<IdolBoard>, anyhow::Error> {
+    let mut previous_totals: HashMap<Uuid, i64> = HashMap::new();
+    let mut boards = Vec::new();
+    for x in database_contents {
+        let (timestamp, idols) = x?;
+
+        // there's no season or day to label it with, so leave it out rather than failing the
+        // whole page
+        let board_time = match get_timestamp_for_board(timestamp, store)? {
+            Some(board_time) => board_time,
+            None => {
+                log::warn!(
+                    "leaving out board {}, from before the first game day",
+                    timestamp
+                );
+                continue;
+            }
+        };
+        let board = convert_idol_board(board_time, idols, timestamp, store, &previous_totals)?;
+
+        // boards without totals break the chain, rather than comparing across them
+        previous_totals = board
+            .players
+            .iter()
+            .filter_map(|player| player.fans.as_ref().map(|fans| (player.id, fans.total)))
             .collect();
 
-            log::info!("timestamp {}", timestamp);
+        log::info!("timestamp {}", timestamp);
+
+        boards.push(board);
+    }
+
+    Ok(boards)
+}
 
-            let timestamp = Timestamp {
-                timestamp,
-                day,
-                time_since_game_start,
+/// Builds a single board, with each player's name and team as they were at `players_at`. Fan
+/// changes are relative to `previous_totals`, which is empty for a board shown on its own.
+fn convert_idol_board(
+    timestamp: Timestamp,
+    idols: IdolsClass,
+    players_at: DateTime<FixedOffset>,
+    store: &Store,
+    previous_totals: &HashMap<Uuid, i64>,
+) -> Result<IdolBoard, anyhow::Error> {
+    let totals = idols.totals.unwrap_or_default();
+    let noodle = idols.data.map(|data| data.strictly_confidential);
+
+    let idol_data = idols
+        .idols
+        .into_iter()
+        .enumerate()
+        .map(|(index, player_id)| {
+            let mut player = match get_displayable_data_for_player(player_id, players_at, store) {
+                Ok(player) => player,
+                // show the rest of the board, rather than failing the whole page
+                Err(err) if err.downcast_ref::<LookupError>().is_some() => {
+                    log::warn!("{:#}", err);
+                    PlayerDisplayable::unknown(player_id)
+                }
+                Err(err) => return Err(err),
             };
-            Ok((timestamp, idol_data))
-        });
+            player.fans = totals.get(index).copied().flatten().map(|total| Fans {
+                total,
+                change: previous_totals
+                    .get(&player_id)
+                    .map(|previous| total - previous),
+            });
+            Ok(player)
+        })
+        .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+    Ok(IdolBoard {
+        timestamp,
+        players: idol_data,
+        noodle,
+        annotations: Vec::new(),
+        diff: None,
+        markers: Vec::new(),
+        previous_gap: None,
+        next_gap: None,
+    })
+}
+
+/// the annotations from `data/elections.json` for `season`
+pub fn get_annotations_for_season(season: SeasonNumber) -> Result<Vec<Annotation>, anyhow::Error> {
+    Ok(get_eras()?
+        .into_iter()
+        .flat_map(|era| era.seasons.into_iter())
+        .find(|season_data| season_data.index == season)
+        .map(|season_data| season_data.annotations)
+        .unwrap_or_default())
+}
 
-    match limit {
-        Some(number_to_limit_to) => idol_boards.take(number_to_limit_to.into()).collect::<Result<Vec<_>, anyhow::Error>>(),
-        None => idol_boards.collect::<Result<Vec<_>, anyhow::Error>>(),
+/// An RFC 3339 timestamp taken from the URL, e.g. `/board/2020-09-06T15:28:03.574Z`.
+pub struct TimestampParam(pub DateTime<FixedOffset>);
+
+impl<'a> FromParam<'a> for TimestampParam {
+    type Error = String;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        TimestampParam::parse(param)
+    }
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for TimestampParam {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        TimestampParam::parse(field.value).map_err(|err| form::Error::validation(err).into())
+    }
+}
+
+impl TimestampParam {
+    /// Only accepts instants that keys can hold, as nanoseconds since 1970 up to some time in
+    /// 2262. Earlier ones would sort after every key, and later ones don't fit.
+    fn parse(value: &str) -> Result<TimestampParam, String> {
+        let timestamp = DateTime::parse_from_rfc3339(value).map_err(|err| err.to_string())?;
+        let nanos = timestamp
+            .timestamp()
+            .checked_mul(1_000_000_000)
+            .and_then(|nanos| nanos.checked_add(timestamp.timestamp_subsec_nanos().into()));
+        match nanos {
+            Some(nanos) if nanos >= 0 => Ok(TimestampParam(timestamp)),
+            _ => Err(format!(
+                "{} is outside the range of times that can be looked up",
+                value
+            )),
+        }
+    }
+
+    /// the same instant, in a form that doesn't need escaping in a URL
+    pub fn to_url_string(&self) -> String {
+        self.0.to_rfc3339_opts(SecondsFormat::AutoSi, true)
     }
 }
 
 fn get_displayable_data_for_player(
     id: Uuid,
     timestamp: DateTime<FixedOffset>,
-    player_tree: &Tree,
-    team_tree: &Tree,
+    store: &Store,
 ) -> Result<PlayerDisplayable, anyhow::Error> {
-    let result = player_tree
-        .get_lt(Key::new(id, timestamp).as_bytes())?
-        .unwrap();
+    let player_data = store.player_at(id, timestamp)?;
 
-    let result_id = Uuid::from_slice(&Key::read_from(result.0.as_bytes()).unwrap().id)?;
-    assert!(result_id == id, "no data existed for player {}", id);
-
-    let player_data: PlayerData = serde_json::from_slice(result.1.as_bytes())?;
-
-    let team = match player_data.team {
-        Some(team_id) => {
-            //log::info!("getting data for team {}", team_id);
-            let team_fetch_result = team_tree.get(team_id.as_bytes())?;
-            if team_fetch_result.is_none() {
-                log::info!("uhhh");
-            }
-            let team: TeamData = serde_json::from_slice(&team_fetch_result.unwrap().as_bytes())?;
-            TeamDisplayable::new(team.full_name, team.colour, team.emoji)
-        }
-        None => TeamDisplayable {
-            name: "nullteam".into(),
-            colour: "#999999".into(),
-            emoji: "❓".into(),
+    let (team, roster) = match player_data.team {
+        Some(team_id) => match store.team_at(team_id, timestamp)? {
+            Some(team) => (TeamDisplayable::new(&team), team.roster_status(id)),
+            // a later version's roster says nothing about where the player was at the time
+            None => (
+                get_displayable_data_for_team(Some(team_id), timestamp, store)?,
+                None,
+            ),
         },
+        None => (TeamDisplayable::null(), None),
     };
 
     Ok(PlayerDisplayable {
         id,
+        ego: player_data.ego(),
         name: player_data.name,
         team,
+        roster,
         deceased: player_data.deceased,
-        ego: 0,
+        fans: None,
     })
 }
 
-fn get_bounds_for_season(
-    season: i16,
-) -> Result<(DateTime<FixedOffset>, DateTime<FixedOffset>), anyhow::Error> {
-    let days_tree = DB.open_tree(DAYS_TREE)?;
-
-    let key = SeasonDayKey {
-        season: season.into(),
-        day: 0,
-    };
-    let timestamp_of_first_day = days_tree
-        .get(key.as_bytes())?
-        .map(|v| DateTime::parse_from_rfc3339(std::str::from_utf8(&v).unwrap()).unwrap())
-        .unwrap_or(DateTime::parse_from_rfc3339(BEGINNING_OF_TIME).unwrap());
-    let key = SeasonDayKey {
-        season: season.into(),
-        day: 255,
-    };
-    let timestamp_of_last_day = days_tree
-        .get(key.as_bytes())?
-        .map(|v| DateTime::parse_from_rfc3339(std::str::from_utf8(&v).unwrap()).unwrap())
-        .unwrap_or(DateTime::parse_from_rfc3339(END_OF_TIME).unwrap());
-
-    Ok((timestamp_of_first_day, timestamp_of_last_day))
-}
-
-fn get_day_and_time_since_game_start(
+fn get_displayable_data_for_team(
+    team_id: Option<Uuid>,
     timestamp: DateTime<FixedOffset>,
-    inverse_days_tree: &Tree
-) -> Result<(u8, f32), anyhow::Error> {
-    let (start_time_bytes, season_day_bytes) = (*inverse_days_tree).get_lt(timestamp.to_rfc3339().as_bytes())?.unwrap();
-
-    let start_time = DateTime::parse_from_rfc3339(std::str::from_utf8(&start_time_bytes).unwrap()).unwrap();
-    let time_since_start_of_game = timestamp - start_time;
-    let fractional_minutes_since_start_of_game = time_since_start_of_game.num_minutes() as f32 + (time_since_start_of_game.num_seconds() as f32 / 60_f32);
-
-    let season_day = SeasonDayKey::read_from(season_day_bytes.as_bytes()).unwrap();
-    Ok((season_day.day, fractional_minutes_since_start_of_game))
+    store: &Store,
+) -> Result<TeamDisplayable, anyhow::Error> {
+    Ok(match team_id {
+        Some(team_id) => match store.team(team_id, timestamp)? {
+            Some(team) => TeamDisplayable::new(&team),
+            // the player is still worth showing without it
+            None => {
+                log::warn!("no data cached for team {}", team_id);
+                TeamDisplayable::unknown()
+            }
+        },
+        None => TeamDisplayable::null(),
+    })
 }
 
-mod routes {
+mod assets {
     macro_rules! asset {
         ($path:expr) => {
             include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/", $path))
@@ -160,35 +417,200 @@ mod routes {
 
 #[get("/styles.css")]
 pub fn css() -> (ContentType, &'static str) {
-    (ContentType::CSS, routes::asset!("/main.css"))
+    (ContentType::CSS, assets::asset!("/main.css"))
 }
 
 #[get("/cardstock.svg")]
 pub fn cardstock() -> (ContentType, &'static str) {
-    (ContentType::SVG, routes::asset!("cardstock.svg"))
+    (ContentType::SVG, assets::asset!("cardstock.svg"))
 }
 
 #[get("/images/nav-first.svg")]
 pub fn nav_first_image() -> (ContentType, &'static str) {
-    (ContentType::SVG, routes::asset!("images/nav-first.svg"))
+    (ContentType::SVG, assets::asset!("images/nav-first.svg"))
 }
 
 #[get("/images/nav-back.svg")]
 pub fn nav_back_image() -> (ContentType, &'static str) {
-    (ContentType::SVG, routes::asset!("images/nav-back.svg"))
+    (ContentType::SVG, assets::asset!("images/nav-back.svg"))
 }
 
 #[get("/images/nav-next.svg")]
 pub fn nav_next_image() -> (ContentType, &'static str) {
-    (ContentType::SVG, routes::asset!("images/nav-next.svg"))
+    (ContentType::SVG, assets::asset!("images/nav-next.svg"))
 }
 
 #[get("/images/nav-last.svg")]
 pub fn nav_last_image() -> (ContentType, &'static str) {
-    (ContentType::SVG, routes::asset!("images/nav-last.svg"))
+    (ContentType::SVG, assets::asset!("images/nav-last.svg"))
 }
 
 #[get("/manifest.webmanifest")]
 pub fn manifest() -> (ContentType, &'static str) {
-    (ContentType::JSON, routes::asset!("manifest.webmanifest"))
+    (ContentType::JSON, assets::asset!("manifest.webmanifest"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::team::RosterStatus;
+    use crate::test_support::{at, board, player, team};
+
+    /// a player who's been on `team_id` since 2020-08-01
+    fn store_with_player(id: Uuid, team_id: Uuid) -> Store {
+        let store = Store::temporary().unwrap();
+        let mut player = player(id, "Known Player");
+        player.team = Some(team_id);
+        store
+            .insert_player_version(id, at("2020-08-01T00:00:00Z").into(), &player)
+            .unwrap();
+        store
+    }
+
+    fn insert_team(store: &Store, team_id: Uuid, valid_from: &str, shadows: &[Uuid]) {
+        let mut team = team(team_id, "Team");
+        team.shadows = shadows.to_vec();
+        store
+            .insert_team_version(at(valid_from).into(), &team)
+            .unwrap();
+    }
+
+    #[test]
+    fn boards_from_before_the_first_game_day_are_left_out() {
+        let id = Uuid::from_u128(1);
+        let store = store_with_player(id, Uuid::from_u128(2));
+        store
+            .insert_game_day(
+                SeasonNumber::from_storage(5),
+                0,
+                at("2020-09-07T16:00:00Z").into(),
+            )
+            .unwrap();
+        for posted in ["2020-09-07T15:00:00Z", "2020-09-07T16:30:00Z"] {
+            store
+                .insert_idol_board(at(posted).into(), &board(&[id]))
+                .unwrap();
+        }
+
+        let boards = convert_db_contents_into_format_for_page(
+            store.idol_boards_between(at("2020-09-07T00:00:00Z"), at("2020-09-08T00:00:00Z")),
+            &store,
+        )
+        .unwrap();
+        assert_eq!(
+            boards
+                .iter()
+                .map(|board| board.timestamp.timestamp)
+                .collect::<Vec<_>>(),
+            [at("2020-09-07T16:30:00Z")]
+        );
+    }
+
+    #[test]
+    fn gap_labels_drop_the_smaller_units_once_there_are_days() {
+        let label = |seconds, game_days| BoardGap { seconds, game_days }.label();
+
+        assert_eq!(label(35 * 60 + 59, 0), "35m");
+        assert_eq!(label(2 * 60 * 60, 0), "2h");
+        assert_eq!(label(2 * 60 * 60 + 5 * 60, 0), "2h 5m");
+        assert_eq!(label(3 * 24 * 60 * 60 + 5 * 60, 0), "3d");
+        assert_eq!(label(3 * 24 * 60 * 60 + 4 * 60 * 60 + 5 * 60, 0), "3d 4h");
+        assert_eq!(label(60 * 60, 1), "1h, 1 game day");
+        assert_eq!(label(6 * 60 * 60, 6), "6h, 6 game days");
+    }
+
+    #[test]
+    fn gaps_count_the_game_days_started_in_between() {
+        let store = Store::temporary().unwrap();
+        for (season, day, start) in [
+            (5, 0, "2020-09-07T16:00:00Z"),
+            (5, 1, "2020-09-07T17:00:00Z"),
+            (5, 2, "2020-09-07T18:00:00Z"),
+            (6, 0, "2020-09-14T16:00:00Z"),
+        ] {
+            store
+                .insert_game_day(SeasonNumber::from_storage(season), day, at(start).into())
+                .unwrap();
+        }
+        let gap = |earlier, later| {
+            let timestamp = |posted| {
+                get_timestamp_for_board(at(posted), &store)
+                    .unwrap()
+                    .unwrap()
+            };
+            BoardGap::between(&timestamp(earlier), &timestamp(later), &store).label()
+        };
+
+        assert_eq!(gap("2020-09-07T16:10:00Z", "2020-09-07T16:45:00Z"), "35m");
+        assert_eq!(
+            gap("2020-09-07T16:30:00Z", "2020-09-07T17:30:00Z"),
+            "1h, 1 game day"
+        );
+        assert_eq!(
+            gap("2020-09-07T16:30:00Z", "2020-09-07T18:35:00Z"),
+            "2h 5m, 2 game days"
+        );
+        assert_eq!(
+            gap("2020-09-07T18:30:00Z", "2020-09-14T16:30:00Z"),
+            "6d 22h, 1 game day"
+        );
+        assert_eq!(
+            gap("2020-09-07T16:30:00Z", "2020-09-14T16:30:00Z"),
+            "7d, 3 game days"
+        );
+    }
+
+    #[test]
+    fn timestamp_params_must_fit_in_a_key() {
+        assert!(TimestampParam::from_param("2020-09-06T15:28:03.574Z").is_ok());
+        assert!(TimestampParam::from_param("2262-04-11T23:47:16Z").is_ok());
+        assert!(TimestampParam::from_param("2262-04-11T23:47:17Z").is_err());
+        assert!(TimestampParam::from_param("1970-01-01T00:00:00Z").is_ok());
+        assert!(TimestampParam::from_param("1969-12-31T23:59:59Z").is_err());
+        assert!(TimestampParam::from_param("1500-01-01T00:00:00Z").is_err());
+        assert!(TimestampParam::from_param("9999-12-31T23:59:59+00:00").is_err());
+    }
+
+    #[test]
+    fn roster_status_comes_from_the_team_at_the_time() {
+        let (id, team_id) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let store = store_with_player(id, team_id);
+        insert_team(&store, team_id, "2020-08-01T00:00:00Z", &[id]);
+        insert_team(&store, team_id, "2020-10-01T00:00:00Z", &[]);
+
+        let roster_at = |timestamp| {
+            get_displayable_data_for_player(id, at(timestamp), &store)
+                .unwrap()
+                .roster
+        };
+        assert!(matches!(
+            roster_at("2020-09-01T00:00:00Z"),
+            Some(RosterStatus::Shadowed)
+        ));
+        assert!(roster_at("2020-11-01T00:00:00Z").is_none());
+    }
+
+    #[test]
+    fn later_rosters_say_nothing_about_earlier_boards() {
+        let (id, team_id) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let store = store_with_player(id, team_id);
+        insert_team(&store, team_id, "2020-10-01T00:00:00Z", &[id]);
+
+        let player =
+            get_displayable_data_for_player(id, at("2020-09-01T00:00:00Z"), &store).unwrap();
+        assert_eq!(player.team.name, "Team");
+        assert!(player.roster.is_none());
+    }
+
+    #[test]
+    fn players_on_uncached_teams_keep_their_names() {
+        let (id, team_id) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        let store = store_with_player(id, team_id);
+
+        let player =
+            get_displayable_data_for_player(id, at("2020-09-01T00:00:00Z"), &store).unwrap();
+        assert_eq!(player.name, "Known Player");
+        assert_eq!(player.team.name, TeamDisplayable::unknown().name);
+        assert!(player.roster.is_none());
+    }
 }
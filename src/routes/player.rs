@@ -1,32 +1,107 @@
-// use crate::entities::player::PlayerDisplayable;
-// use crate::routes::ResponseResult;
-
-// use anyhow::Result;
-// use askama::Template;
-// use chrono::{DateTime, FixedOffset};
-// use rocket::get;
-// use rocket::response::content::RawHtml;
-// use uuid::Uuid;
-
-// #[get("/player/{player_id}")]
-// pub fn player_page(player_id: Uuid) -> ResponseResult<Option<RawHtml<String>>> {
-//     Ok(
-//         match load_player_page(player_id).map_err(anyhow::Error::from)? {
-//             Some(player_page) => Some(RawHtml(player_page.render().map_err(anyhow::Error::from)?)),
-//             None => None,
-//     })
-// }
-
-// fn load_player_page(player_id: Uuid) -> Result<Option<PlayerPage>> {
-//     // let player_data = routes::get_all_data_for_player(player_id)?;
-
-//      Ok(None)
-// }
-
-// #[derive(Template)]
-// #[template("player.html")]
-// struct PlayerPage {
-//     name: String,
-//     id: Uuid,
-//     versions: Vec<(DateTime<FixedOffset>, Vec<PlayerDisplayable>)>,
-// }
+use crate::entities::player::{PlayerData, PlayerDisplayable};
+use crate::routes::{ResponseResult, Timestamp};
+use crate::store::Store;
+
+use anyhow::Result;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use rocket::response::content::RawHtml;
+use rocket::{get, State};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::get_displayable_data_for_team;
+use super::get_timestamp_for_board;
+
+#[get("/player/<player_id>")]
+pub fn player(player_id: Uuid, store: &State<Store>) -> ResponseResult<Option<RawHtml<String>>> {
+    Ok(match load_player_page(player_id, store)? {
+        Some(player_page) => Some(RawHtml(player_page.render().map_err(anyhow::Error::from)?)),
+        None => None,
+    })
+}
+
+pub fn load_player_page(player_id: Uuid, store: &Store) -> Result<Option<PlayerPage>> {
+    let player_data = store.player_versions(player_id)?;
+
+    let name = match player_data.last() {
+        Some((_, latest)) => latest.name.clone(),
+        None => return Ok(None),
+    };
+
+    // chronicler stores a new version for every stat change, so only keep the versions where
+    // something shown on the page actually changed
+    let mut versions = Vec::new();
+    let mut previous: Option<PlayerData> = None;
+    for (valid_from, data) in player_data.into_iter() {
+        if let Some(previous) = &previous {
+            if previous.name == data.name
+                && previous.team == data.team
+                && previous.deceased == data.deceased
+                && previous.ego() == data.ego()
+            {
+                continue;
+            }
+        }
+
+        versions.push(PlayerVersion {
+            valid_from,
+            player: PlayerDisplayable {
+                id: player_id,
+                name: data.name.clone(),
+                team: get_displayable_data_for_team(data.team, valid_from.into(), store)?,
+                roster: None,
+                deceased: data.deceased,
+                ego: data.ego(),
+                fans: None,
+            },
+        });
+        previous = Some(data);
+    }
+
+    let mut boards = Vec::new();
+    let appearances = store
+        .player_index_entry(player_id)?
+        .map(|entry| entry.boards)
+        .unwrap_or_default();
+    for appearance in appearances {
+        // there's no season page to link to for a board from before the first cached game
+        if let Some(timestamp) = get_timestamp_for_board(appearance.timestamp.into(), store)? {
+            boards.push(PlayerBoard {
+                timestamp,
+                rank: appearance.rank,
+            });
+        }
+    }
+
+    Ok(Some(PlayerPage {
+        id: player_id,
+        name,
+        versions,
+        boards,
+    }))
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "player.html")]
+pub struct PlayerPage {
+    id: Uuid,
+    name: String,
+    versions: Vec<PlayerVersion>,
+    boards: Vec<PlayerBoard>,
+}
+
+/// how the player looked from `valid_from` until the next version
+#[derive(Serialize)]
+pub struct PlayerVersion {
+    valid_from: DateTime<Utc>,
+    player: PlayerDisplayable,
+}
+
+/// an idol board the player was on
+#[derive(Serialize)]
+pub struct PlayerBoard {
+    timestamp: Timestamp,
+    /// counted from 1
+    rank: usize,
+}
@@ -0,0 +1,218 @@
+use crate::entities::season::SeasonNumber;
+use crate::routes::ResponseResult;
+use crate::store::Store;
+
+use anyhow::Result;
+use askama::Template;
+use rocket::response::content::RawHtml;
+use rocket::{get, State};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// more than this and the query wasn't specific enough to be worth listing everything
+const MAX_RESULTS: usize = 50;
+
+#[get("/search?<q>")]
+pub fn search(q: Option<String>, store: &State<Store>) -> ResponseResult<RawHtml<String>> {
+    let html_content = load_search(q.as_deref().unwrap_or(""), store)?
+        .render()
+        .map_err(anyhow::Error::from)?;
+
+    Ok(RawHtml(html_content))
+}
+
+/// Every player with a current or former name containing `query`, ignoring case. Exact matches
+/// come first, then names starting with `query`, then the rest alphabetically.
+pub fn load_search(query: &str, store: &Store) -> Result<SearchPage> {
+    let query = query.trim();
+    let needle = query.to_lowercase();
+    if needle.is_empty() {
+        return Ok(SearchPage {
+            query: String::new(),
+            results: Vec::new(),
+            truncated: false,
+        });
+    }
+
+    let mut results = Vec::new();
+    for entry in store.player_index() {
+        let (id, entry) = entry?;
+
+        // prefer matching the current name, so a player is only shown under a former name when
+        // that's the only way they matched
+        let best_match = entry
+            .names
+            .iter()
+            .rev()
+            .filter_map(|name| match_quality(name, &needle).map(|quality| (quality, name)))
+            .min_by_key(|(quality, _)| *quality);
+        let (quality, matched_name) = match best_match {
+            Some(best_match) => best_match,
+            None => continue,
+        };
+
+        let name = match entry.names.last() {
+            Some(name) => name.clone(),
+            None => continue,
+        };
+        results.push((
+            quality,
+            SearchResult {
+                id,
+                former_name: (*matched_name != name).then(|| matched_name.clone()),
+                name,
+                seasons: entry
+                    .seasons
+                    .into_iter()
+                    .map(SeasonNumber::from_storage)
+                    .collect(),
+            },
+        ));
+    }
+
+    results.sort_by(|(a_quality, a), (b_quality, b)| {
+        a_quality
+            .cmp(b_quality)
+            .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+    let truncated = results.len() > MAX_RESULTS;
+    results.truncate(MAX_RESULTS);
+
+    Ok(SearchPage {
+        query: query.to_string(),
+        results: results.into_iter().map(|(_, result)| result).collect(),
+        truncated,
+    })
+}
+
+/// how well `name` matches the lowercased `needle`, lower being better
+fn match_quality(name: &str, needle: &str) -> Option<u8> {
+    let name = name.to_lowercase();
+    if name == needle {
+        Some(0)
+    } else if name.starts_with(needle) {
+        Some(1)
+    } else if name.contains(needle) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "search.html")]
+pub struct SearchPage {
+    query: String,
+    results: Vec<SearchResult>,
+    /// whether there were more than [`MAX_RESULTS`] matches
+    truncated: bool,
+}
+
+#[derive(Serialize)]
+pub struct SearchResult {
+    id: Uuid,
+    /// the player's current name
+    name: String,
+    /// the name that matched, if the player has since been renamed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    former_name: Option<String>,
+    /// the seasons the player was on an idol board in
+    seasons: Vec<SeasonNumber>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::player::PlayerIndexEntry;
+
+    /// a store whose index knows each player by `names`, oldest first
+    fn store_with_players(players: &[&[&str]]) -> Store {
+        let entries = players
+            .iter()
+            .enumerate()
+            .map(|(id, names)| {
+                let entry = PlayerIndexEntry {
+                    names: names.iter().map(|name| name.to_string()).collect(),
+                    ..PlayerIndexEntry::default()
+                };
+                (Uuid::from_u128(id as u128), entry)
+            })
+            .collect::<Vec<_>>();
+        let store = Store::temporary().unwrap();
+        store
+            .replace_player_index(entries.iter().map(|(id, entry)| (id, entry)))
+            .unwrap();
+        store
+    }
+
+    fn found(query: &str, store: &Store) -> Vec<(String, Option<String>)> {
+        load_search(query, store)
+            .unwrap()
+            .results
+            .into_iter()
+            .map(|result| (result.name, result.former_name))
+            .collect()
+    }
+
+    #[test]
+    fn match_quality_ignores_case() {
+        assert_eq!(
+            match_quality("Jessica Telephone", "jessica telephone"),
+            Some(0)
+        );
+        assert_eq!(match_quality("Jessica Telephone", "jess"), Some(1));
+        assert_eq!(match_quality("Jessica Telephone", "phone"), Some(2));
+        assert_eq!(match_quality("Jessica Telephone", "york"), None);
+    }
+
+    #[test]
+    fn exact_matches_then_prefixes_then_the_rest() {
+        let store = store_with_players(&[&["Sam Bell"], &["Bell Sam"], &["Bella"], &["Bell"]]);
+
+        assert_eq!(
+            found(" BELL ", &store),
+            [
+                ("Bell".to_string(), None),
+                ("Bell Sam".to_string(), None),
+                ("Bella".to_string(), None),
+                ("Sam Bell".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn current_names_win_ties_with_former_names() {
+        let store = store_with_players(&[
+            &["Wyatt Mason", "Wyatt Masoned"],
+            &["Wyatt Dovenpart", "Wyatt Quitter"],
+        ]);
+
+        assert_eq!(
+            found("masoned", &store),
+            [("Wyatt Masoned".to_string(), None)]
+        );
+        assert_eq!(
+            found("wyatt m", &store),
+            [("Wyatt Masoned".to_string(), None)]
+        );
+        // an exact match on a former name beats a prefix of the current one
+        assert_eq!(
+            found("wyatt mason", &store),
+            [("Wyatt Masoned".to_string(), Some("Wyatt Mason".to_string()))]
+        );
+        assert_eq!(
+            found("dovenpart", &store),
+            [(
+                "Wyatt Quitter".to_string(),
+                Some("Wyatt Dovenpart".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn blank_queries_find_nothing() {
+        let store = store_with_players(&[&["Anybody"]]);
+
+        assert!(found("   ", &store).is_empty());
+    }
+}
@@ -1,51 +1,546 @@
-use crate::entities::player::PlayerDisplayable;
-use crate::routes::{ResponseResult, Timestamp};
-use crate::{DB, IDOLS_TREE, PLAYER_TREE, TEAM_TREE, INVERSE_DAYS_TREE};
+use crate::entities::season::SeasonNumber;
+use crate::routes::diff::BoardDiff;
+use crate::routes::{IdolBoard, ResponseResult, TimestampParam};
+use crate::store::Store;
 
 use askama::Template;
-use rocket::get;
+use chrono::{DateTime, FixedOffset};
+use rocket::form::{self, FromFormField, ValueField};
 use rocket::response::content::RawHtml;
+use rocket::{get, FromForm, State};
+use serde::Serialize;
+use std::fmt;
 
 use super::convert_db_contents_into_format_for_page;
-use super::get_bounds_for_season;
+use super::get_annotations_for_season;
+use super::get_eras;
+use super::get_timestamp_for_board;
+use super::set_board_gaps;
 
-#[get("/season/<season>?<limit>")]
-pub fn season(season: i16, limit: Option<u16>) -> ResponseResult<Option<RawHtml<String>>> {
+/// the day numbers in `games_v1` carry on through the postseason, which starts after the 99 days
+/// of the regular season
+const FIRST_POSTSEASON_DAY: u8 = 99;
+
+#[get("/season/<season>?<diff>&<window..>")]
+pub fn season(
+    season: SeasonNumber,
+    diff: Option<bool>,
+    window: SeasonWindow,
+    store: &State<Store>,
+) -> ResponseResult<Option<RawHtml<String>>> {
     Ok(
-        match load_season(season - 1, limit).map_err(anyhow::Error::from)? {
+        match load_season(season, &window, diff.unwrap_or(false), store)? {
             Some(idol_boards) => Some(RawHtml(idol_boards.render().map_err(anyhow::Error::from)?)),
             None => None,
         },
     )
 }
 
-fn load_season(season: i16, limit: Option<u16>) -> Result<Option<SeasonPage>, anyhow::Error> {
-    let (timestamp_of_first_day, timestamp_of_last_day) = get_bounds_for_season(season)?;
+/// Which of a season's boards to show. Every bound is optional, and they all narrow the season
+/// down further. With a `limit` only that many boards are shown, starting after `after` or ending
+/// before `before` if either of those is given, and the page links to the boards either side.
+#[derive(FromForm)]
+pub struct SeasonWindow {
+    limit: Option<u16>,
+    from: Option<TimestampParam>,
+    to: Option<TimestampParam>,
+    day: Option<DayRange>,
+    after: Option<TimestampParam>,
+    before: Option<TimestampParam>,
+}
+
+impl SeasonWindow {
+    /// the query string for this window, without the leading `?`
+    fn query(&self) -> String {
+        self.query_with_cursor(
+            self.after
+                .as_ref()
+                .map(|after| ("after", after))
+                .or_else(|| self.before.as_ref().map(|before| ("before", before))),
+        )
+    }
+
+    /// the query string for this window with the cursors replaced
+    fn query_with_cursor(&self, cursor: Option<(&str, &TimestampParam)>) -> String {
+        let mut query = Vec::new();
+        if let Some(limit) = self.limit {
+            query.push(format!("limit={}", limit));
+        }
+        if let Some(from) = &self.from {
+            query.push(format!("from={}", from.to_url_string()));
+        }
+        if let Some(to) = &self.to {
+            query.push(format!("to={}", to.to_url_string()));
+        }
+        if let Some(day) = &self.day {
+            query.push(format!("day={}", day));
+        }
+        if let Some((name, timestamp)) = cursor {
+            query.push(format!("{}={}", name, timestamp.to_url_string()));
+        }
+        query.join("&")
+    }
+}
+
+/// A single day, `day=90`, or an inclusive range of them, `day=90-99`, using the same day numbers
+/// as the board headers.
+pub struct DayRange {
+    first: u8,
+    last: u8,
+}
+
+#[rocket::async_trait]
+impl<'v> FromFormField<'v> for DayRange {
+    fn from_value(field: ValueField<'v>) -> form::Result<'v, Self> {
+        let (first, last) = field
+            .value
+            .split_once('-')
+            .unwrap_or((field.value, field.value));
+        let (first, last) = match (first.trim().parse::<u8>(), last.trim().parse::<u8>()) {
+            (Ok(first), Ok(last)) => (first, last),
+            (Err(err), _) | (_, Err(err)) => {
+                return Err(form::Error::validation(err.to_string()).into())
+            }
+        };
+        if first > last {
+            return Err(form::Error::validation("the first day is after the last").into());
+        }
+        Ok(DayRange { first, last })
+    }
+}
+
+impl fmt::Display for DayRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.first == self.last {
+            write!(f, "{}", self.first)
+        } else {
+            write!(f, "{}-{}", self.first, self.last)
+        }
+    }
+}
+
+/// With `diff`, every board but the first also shows how it differs from the one before it.
+/// `None` if the season doesn't exist.
+pub fn load_season(
+    season: SeasonNumber,
+    window: &SeasonWindow,
+    diff: bool,
+    store: &Store,
+) -> Result<Option<SeasonPage>, anyhow::Error> {
+    if !season_exists(season, store)? {
+        return Ok(None);
+    }
+
+    let (season_start, season_end) = store.season_bounds(season)?;
+    let (mut start, mut end) = (season_start, season_end);
+    if let Some(from) = &window.from {
+        start = start.max(from.0);
+    }
+    if let Some(to) = &window.to {
+        end = end.min(to.0);
+    }
+    if let Some(day) = &window.day {
+        match store.start_of_day_from(season, day.first)? {
+            Some(start_of_first_day) => start = start.max(start_of_first_day),
+            // the days asked for haven't been played yet
+            None => end = start,
+        }
+        if let Some(last) = day.last.checked_add(1) {
+            if let Some(start_of_next_day) = store.start_of_day_from(season, last)? {
+                end = end.min(start_of_next_day);
+            }
+        }
+    }
+
+    let keys = store.idol_board_times(start, end)?;
+
+    // which of the boards in the window are on this page
+    let limit = window.limit.map_or(keys.len(), usize::from);
+    let (first, last) = match (&window.after, &window.before) {
+        (Some(after), _) => {
+            let first = keys.partition_point(|board| *board <= after.0);
+            (first, keys.len().min(first + limit))
+        }
+        (None, Some(before)) => {
+            let last = keys.partition_point(|board| *board < before.0);
+            (last.saturating_sub(limit), last)
+        }
+        (None, None) => (0, keys.len().min(limit)),
+    };
+
+    let mut boards = match (
+        keys.get(first),
+        last.checked_sub(1).and_then(|last| keys.get(last)),
+    ) {
+        (Some(first_key), Some(last_key)) if first < last => {
+            convert_db_contents_into_format_for_page(
+                store.idol_boards_between(*first_key, *last_key),
+                store,
+            )?
+        }
+        _ => Vec::new(),
+    };
 
-    let idols_tree = DB.open_tree(IDOLS_TREE)?;
-    let player_tree = DB.open_tree(PLAYER_TREE)?;
-    let team_tree = DB.open_tree(TEAM_TREE)?;
-    let inverse_days_tree = DB.open_tree(INVERSE_DAYS_TREE)?;
+    let neighbour = |entry: Option<(DateTime<FixedOffset>, _)>| match entry {
+        Some((timestamp, _)) => get_timestamp_for_board(timestamp, store),
+        None => Ok(None),
+    };
+    let (previous_board, next_board) = match (
+        keys.get(first),
+        last.checked_sub(1).and_then(|last| keys.get(last)),
+    ) {
+        (Some(first_key), Some(last_key)) if first < last => (
+            neighbour(store.idol_board_before(*first_key)?)?,
+            neighbour(store.idol_board_after(*last_key)?)?,
+        ),
+        _ => (None, None),
+    };
+    set_board_gaps(&mut boards, previous_board, next_board, store);
+
+    let previous_page = match boards.first() {
+        Some(board) if first > 0 => Some(
+            window.query_with_cursor(Some(("before", &TimestampParam(board.timestamp.timestamp)))),
+        ),
+        _ => None,
+    };
+    let next_page = match boards.last() {
+        Some(board) if last < keys.len() => Some(
+            window.query_with_cursor(Some(("after", &TimestampParam(board.timestamp.timestamp)))),
+        ),
+        _ => None,
+    };
+
+    let annotations = get_annotations_for_season(season)?;
+    for board in boards.iter_mut() {
+        board.apply_annotations(&annotations);
+    }
+
+    if diff {
+        for index in 1..boards.len() {
+            let (earlier, later) = boards.split_at_mut(index);
+            later[0].diff = Some(BoardDiff::between(&earlier[index - 1], &later[0]));
+        }
+    }
+
+    let era_season = get_eras()?
+        .into_iter()
+        .flat_map(|era| era.seasons.into_iter())
+        .find(|season_data| season_data.index == season);
+    let election_start = match era_season.and_then(|season_data| season_data.election_offset) {
+        Some(election_offset) => Some(DateTime::parse_from_rfc3339(&election_offset)?),
+        None => None,
+    };
+    let postseason_start = match store.start_of_day_from(season, FIRST_POSTSEASON_DAY)? {
+        Some(postseason_start) if postseason_start < season_end => Some(postseason_start),
+        _ => None,
+    };
+
+    // the first board of the season posted once each part of it had started, which is marked
+    // wherever it's shown so that it can be linked to
+    let mut jump_to = |marker: SeasonMarker, starts: Option<DateTime<FixedOffset>>| {
+        let board = match starts {
+            Some(starts) => store
+                .idol_board_times(starts, season_end)?
+                .into_iter()
+                .next(),
+            None => None,
+        };
+        Ok::<_, anyhow::Error>(match board {
+            Some(board) => {
+                // found by time rather than by its place in `keys`, since the ingest may have
+                // added or removed boards since they were read
+                let on_page = boards
+                    .iter_mut()
+                    .find(|idol_board| idol_board.timestamp.timestamp == board)
+                    .map(|idol_board| idol_board.markers.push(marker))
+                    .is_some();
+                Some(SeasonJump {
+                    marker,
+                    board,
+                    on_page,
+                })
+            }
+            None => None,
+        })
+    };
+    let postseason = jump_to(SeasonMarker::Postseason, postseason_start)?;
+    let election = jump_to(SeasonMarker::Election, election_start)?;
 
     let page_content = SeasonPage {
-        season: season,
-        boards: convert_db_contents_into_format_for_page(
-            idols_tree.range(
-                timestamp_of_first_day.to_rfc3339().as_bytes()
-                    ..timestamp_of_last_day.to_rfc3339().as_bytes(),
-            ),
-            player_tree,
-            team_tree,
-            &inverse_days_tree,
-            limit,
-        )?,
+        season,
+        previous_season: season_exists(season.previous(), store)?.then_some(season.previous()),
+        next_season: season_exists(season.next(), store)?.then_some(season.next()),
+        boards,
+        diff,
+        query: window.query(),
+        previous_page,
+        next_page,
+        postseason,
+        election,
     };
     Ok(Some(page_content))
 }
 
-#[derive(Template)]
+#[derive(Template, Serialize)]
 #[template(path = "season.html")]
-struct SeasonPage {
-    season: i16,
-    boards: Vec<(Timestamp, Vec<PlayerDisplayable>)>,
+pub struct SeasonPage {
+    season: SeasonNumber,
+    previous_season: Option<SeasonNumber>,
+    next_season: Option<SeasonNumber>,
+    boards: Vec<IdolBoard>,
+    diff: bool,
+    /// the query string for this page, for links that only change `diff`
+    #[serde(skip)]
+    query: String,
+    /// query strings for the boards before and after these ones, if they were cut off by `limit`
+    previous_page: Option<String>,
+    next_page: Option<String>,
+    postseason: Option<SeasonJump>,
+    election: Option<SeasonJump>,
+}
+
+/// A part of the season that starts partway through it.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SeasonMarker {
+    Postseason,
+    Election,
+}
+
+impl SeasonMarker {
+    /// the fragment linking to the board marked with this
+    fn id(&self) -> &'static str {
+        match self {
+            SeasonMarker::Postseason => "postseason",
+            SeasonMarker::Election => "election",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            SeasonMarker::Postseason => "Postseason",
+            SeasonMarker::Election => "Election",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SeasonJump {
+    marker: SeasonMarker,
+    /// the first board posted once it started
+    board: DateTime<FixedOffset>,
+    /// whether that board is one of the ones on this page, rather than cut off by the window
+    on_page: bool,
+}
+
+impl SeasonJump {
+    /// links to the board from another page of the season
+    fn window_query(&self) -> String {
+        format!("from={}", TimestampParam(self.board).to_url_string())
+    }
+}
+
+/// whether the season has any game days cached, or is listed in `data/elections.json`
+fn season_exists(season: SeasonNumber, store: &Store) -> Result<bool, anyhow::Error> {
+    if get_eras()?
+        .iter()
+        .flat_map(|era| era.seasons.iter())
+        .any(|season_data| season_data.index == season)
+    {
+        return Ok(true);
+    }
+
+    store.season_has_game_days(season)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{at, board};
+    use chrono::Duration;
+
+    fn season() -> SeasonNumber {
+        SeasonNumber::from_display(6).unwrap()
+    }
+
+    /// four game days an hour apart from 2020-09-07T16:00Z, with an empty board every 15 minutes
+    /// from 16:05
+    fn store_with_boards() -> (Store, Vec<DateTime<FixedOffset>>) {
+        let store = Store::temporary().unwrap();
+        let first_day = at("2020-09-07T16:00:00Z");
+        for day in 0..4 {
+            store
+                .insert_game_day(
+                    season(),
+                    day,
+                    (first_day + Duration::hours(day.into())).into(),
+                )
+                .unwrap();
+        }
+
+        let board = board(&[]);
+        let times = (0..16)
+            .map(|index| first_day + Duration::minutes(5 + 15 * index))
+            .collect::<Vec<_>>();
+        for time in &times {
+            store.insert_idol_board((*time).into(), &board).unwrap();
+        }
+        (store, times)
+    }
+
+    fn window() -> SeasonWindow {
+        SeasonWindow {
+            limit: None,
+            from: None,
+            to: None,
+            day: None,
+            after: None,
+            before: None,
+        }
+    }
+
+    fn board_times(store: &Store, window: &SeasonWindow) -> Vec<DateTime<FixedOffset>> {
+        load_season(season(), window, false, store)
+            .unwrap()
+            .unwrap()
+            .boards
+            .iter()
+            .map(|board| board.timestamp.timestamp)
+            .collect()
+    }
+
+    #[test]
+    fn after_and_before_leave_out_the_board_they_name() {
+        let (store, times) = store_with_boards();
+
+        let page = SeasonWindow {
+            limit: Some(3),
+            after: Some(TimestampParam(times[4])),
+            ..window()
+        };
+        assert_eq!(board_times(&store, &page), times[5..8]);
+
+        let page = SeasonWindow {
+            limit: Some(3),
+            before: Some(TimestampParam(times[4])),
+            ..window()
+        };
+        assert_eq!(board_times(&store, &page), times[1..4]);
+
+        // a cursor between boards works the same
+        let page = SeasonWindow {
+            limit: Some(3),
+            after: Some(TimestampParam(times[4] + Duration::minutes(1))),
+            ..window()
+        };
+        assert_eq!(board_times(&store, &page), times[5..8]);
+    }
+
+    #[test]
+    fn pages_link_to_the_boards_either_side() {
+        let (store, times) = store_with_boards();
+        let load = |window: &SeasonWindow| {
+            load_season(season(), window, false, &store)
+                .unwrap()
+                .unwrap()
+        };
+
+        let first = load(&SeasonWindow {
+            limit: Some(10),
+            ..window()
+        });
+        assert!(first.previous_page.is_none());
+        assert_eq!(
+            first.next_page.as_deref(),
+            Some("limit=10&after=2020-09-07T18:20:00Z")
+        );
+
+        let last = load(&SeasonWindow {
+            limit: Some(10),
+            after: Some(TimestampParam(times[9])),
+            ..window()
+        });
+        assert_eq!(last.boards.len(), 6);
+        assert!(last.next_page.is_none());
+        assert_eq!(
+            last.previous_page.as_deref(),
+            Some("limit=10&before=2020-09-07T18:35:00Z")
+        );
+
+        let before_the_start = load(&SeasonWindow {
+            limit: Some(10),
+            before: Some(TimestampParam(times[0])),
+            ..window()
+        });
+        assert!(before_the_start.boards.is_empty());
+    }
+
+    #[test]
+    fn days_narrow_the_season_down() {
+        let (store, times) = store_with_boards();
+
+        let days = |first, last| SeasonWindow {
+            day: Some(DayRange { first, last }),
+            ..window()
+        };
+        assert_eq!(board_times(&store, &days(1, 1)), times[4..8]);
+        assert_eq!(board_times(&store, &days(2, 3)), times[8..]);
+        assert!(board_times(&store, &days(120, 130)).is_empty());
+    }
+
+    #[test]
+    fn the_postseason_is_marked_on_its_first_board() {
+        let (store, times) = store_with_boards();
+        store
+            .insert_game_day(
+                season(),
+                FIRST_POSTSEASON_DAY,
+                at("2020-09-07T17:30:00Z").into(),
+            )
+            .unwrap();
+
+        let page = load_season(
+            season(),
+            &SeasonWindow {
+                limit: Some(3),
+                after: Some(TimestampParam(times[4])),
+                ..window()
+            },
+            false,
+            &store,
+        )
+        .unwrap()
+        .unwrap();
+
+        let postseason = page.postseason.unwrap();
+        assert_eq!(postseason.board, times[6]);
+        assert!(postseason.on_page);
+        let marked = page
+            .boards
+            .iter()
+            .map(|board| board.markers.iter().map(|marker| marker.id()).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(marked, [vec![], vec!["postseason"], vec![]]);
+    }
+
+    #[test]
+    fn unknown_seasons_are_not_found() {
+        let (store, _) = store_with_boards();
+        let unknown = SeasonNumber::from_display(40).unwrap();
+        assert!(load_season(unknown, &window(), false, &store)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn from_after_to_shows_nothing() {
+        let (store, times) = store_with_boards();
+
+        let backwards = SeasonWindow {
+            from: Some(TimestampParam(times[8])),
+            to: Some(TimestampParam(times[4])),
+            ..window()
+        };
+        assert!(board_times(&store, &backwards).is_empty());
+    }
 }
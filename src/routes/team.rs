@@ -0,0 +1,110 @@
+use crate::entities::season::SeasonNumber;
+use crate::entities::team::{RosterStatus, TeamData, TeamDisplayable};
+use crate::routes::ResponseResult;
+use crate::store::Store;
+use crate::END_OF_TIME;
+
+use anyhow::Result;
+use askama::Template;
+use chrono::DateTime;
+use rocket::response::content::RawHtml;
+use rocket::{get, State};
+use serde::Serialize;
+use uuid::Uuid;
+
+#[get("/team/<team_id>")]
+pub fn team(team_id: Uuid, store: &State<Store>) -> ResponseResult<Option<RawHtml<String>>> {
+    Ok(match load_team_page(team_id, store)? {
+        Some(team_page) => Some(RawHtml(team_page.render().map_err(anyhow::Error::from)?)),
+        None => None,
+    })
+}
+
+/// Every player who was on an idol board while they played for the team, going by the version
+/// of the player current when each board was posted. The ingest works these out, so a team
+/// whose page is asked for mid-ingest may not have any yet.
+pub fn load_team_page(team_id: Uuid, store: &Store) -> Result<Option<TeamPage>> {
+    let latest = match store.team_at(team_id, DateTime::parse_from_rfc3339(END_OF_TIME)?)? {
+        Some(latest) => latest,
+        None => return Ok(None),
+    };
+    let index = store.team_index(team_id)?.unwrap_or_default();
+
+    let seasons = index
+        .seasons
+        .into_iter()
+        .map(|season| TeamSeason {
+            season: SeasonNumber::from_storage(season.season),
+            appearances: season.appearances,
+            players: season.players,
+        })
+        .collect();
+
+    let mut players = index
+        .players
+        .into_iter()
+        .map(|player| TeamPlayer {
+            id: player.id,
+            name: player.name,
+            appearances: player.appearances,
+            best_rank: player.best_rank,
+            roster: player.roster,
+            seasons: player
+                .seasons
+                .into_iter()
+                .map(SeasonNumber::from_storage)
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    players.sort_by(|a, b| {
+        b.appearances
+            .cmp(&a.appearances)
+            .then_with(|| a.best_rank.cmp(&b.best_rank))
+    });
+
+    Ok(Some(TeamPage {
+        id: team_id,
+        team: TeamDisplayable::new(&latest),
+        shadows: latest.shadows().count(),
+        latest,
+        seasons,
+        players,
+    }))
+}
+
+#[derive(Template, Serialize)]
+#[template(path = "team.html")]
+pub struct TeamPage {
+    id: Uuid,
+    team: TeamDisplayable,
+    /// everything cached about the team's newest version
+    latest: TeamData,
+    /// the size of `latest`'s shadows, including the bench and bullpen of older versions
+    shadows: usize,
+    /// only the seasons where one of the team's players was on a board
+    seasons: Vec<TeamSeason>,
+    /// most board appearances first
+    players: Vec<TeamPlayer>,
+}
+
+#[derive(Serialize)]
+pub struct TeamSeason {
+    season: SeasonNumber,
+    /// boards the team's players appeared on, counting each player separately
+    appearances: usize,
+    /// different players from the team on the season's boards
+    players: usize,
+}
+
+#[derive(Serialize)]
+pub struct TeamPlayer {
+    id: Uuid,
+    /// the player's name at their latest appearance for the team
+    name: String,
+    /// boards the player appeared on while on the team
+    appearances: usize,
+    best_rank: usize,
+    /// where the player was on the roster at their latest appearance for the team
+    roster: Option<RosterStatus>,
+    seasons: Vec<SeasonNumber>,
+}
@@ -0,0 +1,305 @@
+use crate::config::CardstockConfig;
+use crate::entities::idol::Idols;
+use crate::entities::player::PlayerData;
+use crate::entities::team::TeamData;
+use crate::{Chron1Versions, Chron2Response, ChronV2Versions, GameData};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::{Client, StatusCode, Url};
+use rocket::tokio::sync::Mutex;
+use rocket::tokio::time::{sleep, sleep_until, Duration, Instant};
+use serde::de::DeserializeOwned;
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+const MAX_ATTEMPTS: u32 = 6;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// how long a request can take, reading the body included, before it's given up on and retried
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Where `start_task` reads idol boards, games, teams and player versions from.
+///
+/// Chosen with the `ingest_source` setting, which takes one of
+/// - `files` or `files:<directory>`: `idols.json`, `games.json` and `teams.json` from the given
+///   directory (`data_directory` by default, as copied into the docker image), with newer team
+///   versions and every player version fetched from `chronicler_base`
+/// - `snapshots:<directory>`: the same two files and a `teams.json`, plus a `players/<uuid>.json`
+///   per player in the shape chronicler's `v2/versions` returns, so an ingest never touches the
+///   network
+/// - `chronicler:<base url>`: everything fetched from a chronicler instance, e.g. a local mock
+///
+/// Requests to chronicler are spaced out to `chronicler_requests_per_second`.
+pub enum IngestSource {
+    LocalFiles(PathBuf, Chronicler),
+    Snapshots(PathBuf),
+    Chronicler(Chronicler),
+}
+
+impl IngestSource {
+    pub fn from_config(config: &CardstockConfig) -> Result<IngestSource> {
+        let source = config.ingest_source.as_str();
+        let (kind, argument) = match source.split_once(':') {
+            Some((kind, argument)) => (kind, Some(argument)),
+            None => (source, None),
+        };
+
+        match (kind, argument) {
+            ("files", directory) => Ok(IngestSource::LocalFiles(
+                directory
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| config.data_directory.clone()),
+                Chronicler::new(&config.chronicler_base, config)?,
+            )),
+            ("snapshots", Some(directory)) => Ok(IngestSource::Snapshots(directory.into())),
+            ("chronicler", Some(base)) => {
+                Ok(IngestSource::Chronicler(Chronicler::new(base, config)?))
+            }
+            _ => Err(anyhow!(
+                "unrecognised ingest source {:?}, expected files[:<directory>], snapshots:<directory> or chronicler:<base url>",
+                source
+            )),
+        }
+    }
+
+    pub async fn idol_board_versions(&self) -> Result<Vec<ChronV2Versions<Idols>>> {
+        match self {
+            IngestSource::LocalFiles(directory, _) | IngestSource::Snapshots(directory) => {
+                let response: Chron2Response<Idols> = read_json(&directory.join("idols.json"))?;
+                Ok(response.items)
+            }
+            IngestSource::Chronicler(chronicler) => chronicler.fetch_all_versions("Idols").await,
+        }
+    }
+
+    pub async fn games(&self) -> Result<Vec<Chron1Versions>> {
+        let games: GameData = match self {
+            IngestSource::LocalFiles(directory, _) | IngestSource::Snapshots(directory) => {
+                read_json(&directory.join("games.json"))?
+            }
+            IngestSource::Chronicler(chronicler) => {
+                chronicler
+                    .fetch(&format!("{}/v1/games", chronicler.base))
+                    .await?
+            }
+        };
+        Ok(games.data)
+    }
+
+    /// The team versions in `teams.json`, which is all a snapshot has, and lets the files source
+    /// show teams without reaching chronicler. It only has one version per team, from whenever
+    /// the file was saved.
+    pub fn local_team_versions(&self) -> Result<Vec<ChronV2Versions<TeamData>>> {
+        match self {
+            IngestSource::Snapshots(directory) => read_json(&directory.join("teams.json")),
+            IngestSource::LocalFiles(directory, _) => {
+                let path = directory.join("teams.json");
+                if path.exists() {
+                    read_json(&path)
+                } else {
+                    log::warn!("{} is missing, only fetching teams", path.display());
+                    Ok(Vec::new())
+                }
+            }
+            IngestSource::Chronicler(_) => Ok(Vec::new()),
+        }
+    }
+
+    /// one page of versions of every team newer than `after`, starting from `page` if chronicler
+    /// gave us a token, or `None` for snapshots, which never touch the network
+    pub async fn team_versions(
+        &self,
+        after: Option<DateTime<Utc>>,
+        page: Option<&str>,
+    ) -> Result<Option<Chron2Response<TeamData>>> {
+        let filter = VersionsFilter {
+            id: None,
+            after,
+            page,
+        };
+        match self {
+            IngestSource::LocalFiles(_, chronicler) | IngestSource::Chronicler(chronicler) => {
+                Ok(Some(chronicler.fetch_versions_page("Team", filter).await?))
+            }
+            IngestSource::Snapshots(_) => Ok(None),
+        }
+    }
+
+    /// one page of versions for a player newer than `after`, starting from `page` if chronicler
+    /// gave us a token
+    pub async fn player_versions(
+        &self,
+        player: &Uuid,
+        after: Option<DateTime<Utc>>,
+        page: Option<&str>,
+    ) -> Result<Chron2Response<PlayerData>> {
+        let filter = VersionsFilter {
+            id: Some(player),
+            after,
+            page,
+        };
+        match self {
+            IngestSource::LocalFiles(_, chronicler) | IngestSource::Chronicler(chronicler) => {
+                chronicler.fetch_versions_page("Player", filter).await
+            }
+            IngestSource::Snapshots(directory) => {
+                let mut response: Chron2Response<PlayerData> =
+                    read_json(&directory.join("players").join(format!("{}.json", player)))?;
+                if let Some(after) = after {
+                    response.items.retain(|version| version.valid_from > after);
+                }
+                // the file holds every version there is, whatever page chronicler had when it
+                // was saved, so asking for the next page would just read it again
+                response.next_page = None;
+                Ok(response)
+            }
+        }
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    log::info!("read data from {}", path.display());
+    serde_json::from_str(&contents).with_context(|| format!("failed to parse {}", path.display()))
+}
+
+#[derive(Default)]
+struct VersionsFilter<'a> {
+    id: Option<&'a Uuid>,
+    after: Option<DateTime<Utc>>,
+    page: Option<&'a str>,
+}
+
+/// A chronicler instance, with requests spaced out and retried when it is having a bad time.
+pub struct Chronicler {
+    base: String,
+    client: Client,
+    request_interval: Duration,
+    next_request: Mutex<Instant>,
+}
+
+impl Chronicler {
+    /// a chronicler at `base`, with the user agent and request rate from `config`
+    pub fn new(base: &str, config: &CardstockConfig) -> Result<Chronicler> {
+        let requests_per_second = config.chronicler_requests_per_second;
+        if !(requests_per_second > 0_f64 && requests_per_second.is_finite()) {
+            return Err(anyhow!(
+                "chronicler_requests_per_second must be positive, got {}",
+                requests_per_second
+            ));
+        }
+
+        Ok(Chronicler {
+            base: base.trim_end_matches('/').into(),
+            client: Client::builder()
+                .user_agent(&config.user_agent)
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+            request_interval: Duration::from_secs_f64(1_f64 / requests_per_second),
+            next_request: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// waits until the next request slot is free, so concurrent fetches share one request rate
+    async fn wait_for_request_slot(&self) {
+        let slot = {
+            let mut next_request = self.next_request.lock().await;
+            let slot = (*next_request).max(Instant::now());
+            *next_request = slot + self.request_interval;
+            slot
+        };
+        sleep_until(slot).await;
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+
+        loop {
+            self.wait_for_request_slot().await;
+            log::info!("performing request to {}", url);
+
+            let error = match self.client.get(url).send().await {
+                Ok(response)
+                    if response.status() == StatusCode::TOO_MANY_REQUESTS
+                        || response.status().is_server_error() =>
+                {
+                    anyhow!("{} responded with {}", url, response.status())
+                }
+                // a body cut off partway through is worth another go, like a failed connection
+                Ok(response) => match response.error_for_status()?.bytes().await {
+                    Ok(body) => match serde_json::from_slice(&body) {
+                        Ok(parsed) => return Ok(parsed),
+                        Err(err) => anyhow::Error::from(err)
+                            .context(format!("failed to parse response from {}", url)),
+                    },
+                    Err(err) => anyhow::Error::from(err)
+                        .context(format!("failed to read response from {}", url)),
+                },
+                Err(err) if err.is_timeout() || err.is_connect() => anyhow::Error::from(err),
+                Err(err) => return Err(err.into()),
+            };
+
+            if attempt == MAX_ATTEMPTS {
+                return Err(error.context(format!("giving up after {} attempts", attempt)));
+            }
+
+            log::warn!("{:#}, retrying in {:?}", error, backoff);
+            sleep(backoff).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
+    async fn fetch_versions_page<T: DeserializeOwned>(
+        &self,
+        entity_type: &str,
+        filter: VersionsFilter<'_>,
+    ) -> Result<Chron2Response<T>> {
+        let mut url = Url::parse(&format!("{}/v2/versions", self.base))?;
+        {
+            let mut query = url.query_pairs_mut();
+            query.append_pair("type", entity_type);
+            if let Some(id) = filter.id {
+                query.append_pair("id", &id.to_string());
+            }
+            if let Some(after) = filter.after {
+                query.append_pair("after", &after.to_rfc3339_opts(SecondsFormat::AutoSi, true));
+            }
+            if let Some(page) = filter.page {
+                query.append_pair("page", page);
+            }
+        }
+        self.fetch(url.as_str()).await
+    }
+
+    async fn fetch_all_versions<T: DeserializeOwned>(
+        &self,
+        entity_type: &str,
+    ) -> Result<Vec<ChronV2Versions<T>>> {
+        let mut versions = Vec::new();
+        let mut page = None;
+
+        loop {
+            let filter = VersionsFilter {
+                page: page.as_deref(),
+                ..Default::default()
+            };
+            let mut response: Chron2Response<T> =
+                self.fetch_versions_page(entity_type, filter).await?;
+            if response.items.is_empty() {
+                break;
+            }
+            versions.append(&mut response.items);
+
+            page = response.next_page;
+            if page.is_none() {
+                break;
+            }
+        }
+
+        Ok(versions)
+    }
+}
@@ -0,0 +1,25 @@
+use chrono::{DateTime, FixedOffset};
+use std::fmt;
+use uuid::Uuid;
+
+/// Something a page asked for that isn't in the cache.
+#[derive(Debug)]
+pub enum LookupError {
+    /// the timestamp is before the first game day in `games_v1`
+    NoGameDayYet(DateTime<FixedOffset>),
+    /// no version of the player in `players_v1` is valid at the time asked for
+    UnknownPlayer(Uuid),
+}
+
+impl fmt::Display for LookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LookupError::NoGameDayYet(timestamp) => {
+                write!(f, "no game day has been cached before {}", timestamp)
+            }
+            LookupError::UnknownPlayer(id) => write!(f, "no data cached for player {}", id),
+        }
+    }
+}
+
+impl std::error::Error for LookupError {}
@@ -0,0 +1,316 @@
+//! Changes to how the database is laid out, applied in order whenever it's opened.
+//!
+//! The schema version stored in the `metadata` tree is how many of [`MIGRATIONS`] have been
+//! applied. Migrations name the trees they touch outright, rather than through the constants in
+//! the parent module, so that they keep doing the same thing once those move on.
+
+use super::TimeKey;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::DateTime;
+use sled::Db;
+use zerocopy::AsBytes;
+
+const METADATA_TREE: &str = "metadata";
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+struct Migration {
+    description: &'static str,
+    /// makes the change, or with `dry_run` only works out what it would be, returning a line for
+    /// each thing changed
+    apply: fn(db: &Db, dry_run: bool) -> Result<Vec<String>>,
+}
+
+/// Only ever added to at the end, since a database's schema version is an index into this.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description:
+            "key idol boards and game day starts by nanoseconds instead of RFC 3339 strings",
+        apply: timestamp_keys,
+    },
+    Migration {
+        description: "drop teams_v1, which was replaced by teams_v2",
+        apply: drop_teams_v1,
+    },
+    Migration {
+        description: "drop player_index_v1, which numbered seasons from 1",
+        apply: drop_player_index_v1,
+    },
+];
+
+/// the schema version of a database with every migration applied
+pub const LATEST_SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// What a migration changed, or would change on a dry run.
+pub struct MigrationReport {
+    /// the schema version the migration brings the database up to
+    pub version: u32,
+    pub description: &'static str,
+    pub changes: Vec<String>,
+}
+
+/// Applies every migration the database hasn't had yet, recording the schema version after each
+/// one so that an interrupted run carries on from there. With `dry_run` nothing is written, and
+/// the reports say what would have been changed.
+pub fn migrate(db: &Db, dry_run: bool) -> Result<Vec<MigrationReport>> {
+    let current = match schema_version(db)? {
+        Some(version) => version,
+        // there's nothing to migrate in a new database, it's already laid out the latest way
+        None if is_new(db) => {
+            if !dry_run {
+                set_schema_version(db, LATEST_SCHEMA_VERSION)?;
+            }
+            return Ok(Vec::new());
+        }
+        // from before schema versions were recorded
+        None => 0,
+    };
+    if current > LATEST_SCHEMA_VERSION {
+        return Err(anyhow!(
+            "the database is at schema version {}, but this build only knows up to {}",
+            current,
+            LATEST_SCHEMA_VERSION
+        ));
+    }
+
+    let mut reports = Vec::new();
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = index as u32 + 1;
+        if !dry_run {
+            log::info!(
+                "migrating to schema version {}: {}",
+                version,
+                migration.description
+            );
+        }
+
+        let changes = (migration.apply)(db, dry_run)
+            .with_context(|| format!("migration to schema version {} failed", version))?;
+        if !dry_run {
+            for change in &changes {
+                log::info!("  {}", change);
+            }
+            set_schema_version(db, version)?;
+        }
+
+        reports.push(MigrationReport {
+            version,
+            description: migration.description,
+            changes,
+        });
+    }
+
+    Ok(reports)
+}
+
+/// `None` if the database predates schema versions, or is new
+pub fn schema_version(db: &Db) -> Result<Option<u32>> {
+    // opening the tree would create it, which a dry run shouldn't do
+    if !has_tree(db, METADATA_TREE) {
+        return Ok(None);
+    }
+
+    match db.open_tree(METADATA_TREE)?.get(SCHEMA_VERSION_KEY)? {
+        Some(bytes) => {
+            let bytes = <[u8; 4]>::try_from(bytes.as_ref())
+                .map_err(|_| anyhow!("the stored schema version isn't a u32"))?;
+            Ok(Some(u32::from_be_bytes(bytes)))
+        }
+        None => Ok(None),
+    }
+}
+
+fn set_schema_version(db: &Db, version: u32) -> Result<()> {
+    db.open_tree(METADATA_TREE)?
+        .insert(SCHEMA_VERSION_KEY, &version.to_be_bytes())?;
+    db.flush()?;
+    Ok(())
+}
+
+fn has_tree(db: &Db, name: &str) -> bool {
+    db.tree_names().iter().any(|tree| tree == name.as_bytes())
+}
+
+/// whether there's nothing in the database but sled's own default tree, which Cardstock doesn't
+/// use
+fn is_new(db: &Db) -> bool {
+    db.tree_names()
+        .iter()
+        .all(|tree| *tree == db.name() || tree == METADATA_TREE.as_bytes())
+}
+
+/// The `_v1` trees keyed by time used RFC 3339 strings, which only sort in time order while every
+/// key has the same offset and precision. Copying is repeated in full if it's interrupted, since
+/// the old trees are only dropped once their entries are all in the new ones.
+fn timestamp_keys(db: &Db, dry_run: bool) -> Result<Vec<String>> {
+    let mut changes = Vec::new();
+    for (old_name, new_name) in [
+        ("idols_v1", "idols_v2"),
+        ("inverse_games_v1", "inverse_games_v2"),
+        ("idols_raw_v1", "idols_raw_v2"),
+    ] {
+        if !has_tree(db, old_name) {
+            continue;
+        }
+
+        let old_tree = db.open_tree(old_name)?;
+        let mut batch = sled::Batch::default();
+        let mut migrated = 0;
+        for entry in old_tree.iter() {
+            let (key, value) = entry?;
+            let timestamp = DateTime::parse_from_rfc3339(std::str::from_utf8(&key)?)
+                .with_context(|| format!("unreadable key in {}", old_name))?;
+            batch.insert(TimeKey::new(&timestamp).as_bytes(), value);
+            migrated += 1;
+        }
+        if !dry_run {
+            db.open_tree(new_name)?.apply_batch(batch)?;
+            db.drop_tree(old_name)?;
+        }
+
+        changes.push(format!(
+            "copy {} entries from {} to {} and drop {}",
+            migrated, old_name, new_name, old_name
+        ));
+    }
+
+    Ok(changes)
+}
+
+/// Teams have been kept in `teams_v2` with every version since it was added, and the ingest fills
+/// it from scratch, so the old tree is just taking up space.
+fn drop_teams_v1(db: &Db, dry_run: bool) -> Result<Vec<String>> {
+    if !has_tree(db, "teams_v1") {
+        return Ok(Vec::new());
+    }
+
+    let teams = db.open_tree("teams_v1")?.len();
+    if !dry_run {
+        db.drop_tree("teams_v1")?;
+    }
+
+    Ok(vec![format!("drop teams_v1 and its {} teams", teams)])
+}
+
+/// The search index is rebuilt in `player_index_v2` after every ingest, so there's nothing in the
+/// old tree worth converting.
+fn drop_player_index_v1(db: &Db, dry_run: bool) -> Result<Vec<String>> {
+    if !has_tree(db, "player_index_v1") {
+        return Ok(Vec::new());
+    }
+
+    if !dry_run {
+        db.drop_tree("player_index_v1")?;
+    }
+
+    Ok(vec!["drop player_index_v1".to_string()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::season::SeasonNumber;
+    use crate::store::{SeasonDayKey, Store};
+    use crate::test_support::at;
+
+    fn temporary_db() -> Db {
+        sled::Config::new().temporary(true).open().unwrap()
+    }
+
+    /// a database from before schema versions, with a board and a game day in the `_v1` trees
+    fn unversioned_db() -> Db {
+        let db = temporary_db();
+        for timestamp in ["2020-08-01T00:00:00+00:00", "2020-08-01T00:00:00.25+00:00"] {
+            db.open_tree("idols_v1")
+                .unwrap()
+                .insert(timestamp, r#"{"idols":[]}"#)
+                .unwrap();
+        }
+        db.open_tree("inverse_games_v1")
+            .unwrap()
+            .insert(
+                "2020-07-31T16:00:00+00:00",
+                SeasonDayKey::new(SeasonNumber::from_storage(2), 0).as_bytes(),
+            )
+            .unwrap();
+        db.open_tree("teams_v1")
+            .unwrap()
+            .insert("team", "{}")
+            .unwrap();
+        db.open_tree("player_index_v1")
+            .unwrap()
+            .insert("player", r#"{"names":[],"seasons":[3]}"#)
+            .unwrap();
+        db
+    }
+
+    #[test]
+    fn new_databases_start_at_the_latest_version() {
+        let db = temporary_db();
+        assert!(migrate(&db, false).unwrap().is_empty());
+        assert_eq!(schema_version(&db).unwrap(), Some(LATEST_SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn unversioned_databases_are_migrated() {
+        let db = unversioned_db();
+        let store = Store::from_db(db.clone()).unwrap();
+
+        assert_eq!(schema_version(&db).unwrap(), Some(LATEST_SCHEMA_VERSION));
+        for old_tree in [
+            "idols_v1",
+            "inverse_games_v1",
+            "teams_v1",
+            "player_index_v1",
+        ] {
+            assert!(!has_tree(&db, old_tree));
+        }
+        assert_eq!(
+            store
+                .idol_board_times(at("2020-07-01T00:00:00Z"), at("2020-09-01T00:00:00Z"))
+                .unwrap(),
+            vec![at("2020-08-01T00:00:00Z"), at("2020-08-01T00:00:00.25Z")]
+        );
+        let (season, day, _) = store
+            .game_day_at(at("2020-08-01T00:00:00Z"))
+            .unwrap()
+            .unwrap();
+        assert_eq!((season, day), (SeasonNumber::from_storage(2), 0));
+    }
+
+    #[test]
+    fn dry_runs_change_nothing() {
+        let db = unversioned_db();
+        let reports = migrate(&db, true).unwrap();
+
+        assert_eq!(reports.len(), MIGRATIONS.len());
+        assert_eq!(reports[0].changes.len(), 2);
+        assert_eq!(reports[1].changes.len(), 1);
+        assert_eq!(reports[2].changes.len(), 1);
+        assert_eq!(schema_version(&db).unwrap(), None);
+        assert!(!has_tree(&db, METADATA_TREE));
+        assert!(has_tree(&db, "idols_v1") && !has_tree(&db, "idols_v2"));
+        assert!(has_tree(&db, "teams_v1"));
+        assert!(has_tree(&db, "player_index_v1"));
+    }
+
+    #[test]
+    fn migrations_pick_up_from_the_stored_version() {
+        let db = unversioned_db();
+        set_schema_version(&db, 1).unwrap();
+
+        let reports = migrate(&db, false).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].version, 2);
+        // the first migration was skipped, so the old boards were left where they were
+        assert!(has_tree(&db, "idols_v1"));
+        assert!(!has_tree(&db, "teams_v1"));
+    }
+
+    #[test]
+    fn newer_databases_are_refused() {
+        let db = temporary_db();
+        set_schema_version(&db, LATEST_SCHEMA_VERSION + 1).unwrap();
+        assert!(migrate(&db, false).is_err());
+    }
+}
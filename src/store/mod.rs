@@ -0,0 +1,730 @@
+//! The sled database, and how everything Cardstock caches is laid out in it.
+
+use crate::entities::idol::{Idols, IdolsClass};
+use crate::entities::player::{PlayerData, PlayerIndexEntry};
+use crate::entities::season::SeasonNumber;
+use crate::entities::team::{TeamData, TeamIndexEntry};
+use crate::{BEGINNING_OF_TIME, END_OF_TIME};
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sled::{Db, IVec, Tree};
+use std::collections::HashSet;
+use std::path::Path;
+use uuid::Uuid;
+use zerocopy::{AsBytes, BigEndian, FromBytes, I16, I64};
+
+mod error;
+mod migrations;
+
+pub use error::LookupError;
+pub use migrations::MigrationReport;
+
+const DAYS_TREE: &str = "games_v1";
+const INVERSE_DAYS_TREE: &str = "inverse_games_v2";
+const PLAYER_TREE: &str = "players_v1";
+const IDOLS_TREE: &str = "idols_v2";
+const TEAM_TREE: &str = "teams_v2";
+const PLAYER_INGEST_TREE: &str = "player_ingest_v1";
+const IDOLS_RAW_TREE: &str = "idols_raw_v2";
+const PLAYER_INDEX_TREE: &str = "player_index_v2";
+const TEAM_INGEST_TREE: &str = "team_ingest_v1";
+const TEAM_INDEX_TREE: &str = "team_index_v1";
+
+/// the one key in `team_ingest`, since teams are all fetched together
+const TEAM_INGEST_KEY: &str = "teams";
+
+/// How far through chronicler's versions for a player, or for every team, the ingest has got, so
+/// an interrupted fetch can pick up where it left off and later runs only ask for newer versions.
+#[derive(Serialize, Deserialize)]
+pub struct IngestCheckpoint {
+    pub valid_from: Option<DateTime<Utc>>,
+    pub next_page: Option<PendingPage>,
+}
+
+/// A page token, along with the `after` of the query that produced it.
+#[derive(Serialize, Deserialize)]
+pub struct PendingPage {
+    pub page: String,
+    pub after: Option<DateTime<Utc>>,
+}
+
+/// Every tree Cardstock keeps, opened once at startup and managed as Rocket state.
+pub struct Store {
+    /// `SeasonDayKey` to the RFC 3339 time the day's first game started
+    days: Tree,
+    /// the `TimeKey` of each day's start to its `SeasonDayKey`, for finding which day a timestamp
+    /// falls in
+    inverse_days: Tree,
+    /// `Key` to `PlayerData`
+    players: Tree,
+    /// the `TimeKey` of when a board was posted to `IdolsClass`
+    idols: Tree,
+    /// `Key` to `TeamData`
+    teams: Tree,
+    /// player id to `IngestCheckpoint`
+    player_ingest: Tree,
+    /// like `idols`, but without consecutive duplicates collapsed
+    idols_raw: Tree,
+    /// player id to `PlayerIndexEntry`
+    player_index: Tree,
+    /// `TEAM_INGEST_KEY` to the `IngestCheckpoint` for every team
+    team_ingest: Tree,
+    /// team id to `TeamIndexEntry`
+    team_index: Tree,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> Result<Store> {
+        Store::from_db(open_db(path)?)
+    }
+
+    /// What opening the database at `path` would migrate, without changing anything. Unlike
+    /// [`Store::open`], this won't create a database that isn't there.
+    pub fn dry_run_migrations(path: &Path) -> Result<(Option<u32>, Vec<MigrationReport>)> {
+        if !path.exists() {
+            return Err(anyhow!("there's no database at {}", path.display()));
+        }
+
+        let db = open_db(path)?;
+        Ok((
+            migrations::schema_version(&db)?,
+            migrations::migrate(&db, true)?,
+        ))
+    }
+
+    /// an empty store that's deleted once dropped
+    #[cfg(test)]
+    pub fn temporary() -> Result<Store> {
+        Store::from_db(sled::Config::new().temporary(true).open()?)
+    }
+
+    fn from_db(db: Db) -> Result<Store> {
+        migrations::migrate(&db, false)?;
+
+        Ok(Store {
+            days: db.open_tree(DAYS_TREE)?,
+            inverse_days: db.open_tree(INVERSE_DAYS_TREE)?,
+            players: db.open_tree(PLAYER_TREE)?,
+            idols: db.open_tree(IDOLS_TREE)?,
+            teams: db.open_tree(TEAM_TREE)?,
+            player_ingest: db.open_tree(PLAYER_INGEST_TREE)?,
+            idols_raw: db.open_tree(IDOLS_RAW_TREE)?,
+            player_index: db.open_tree(PLAYER_INDEX_TREE)?,
+            team_ingest: db.open_tree(TEAM_INGEST_TREE)?,
+            team_index: db.open_tree(TEAM_INDEX_TREE)?,
+        })
+    }
+
+    pub fn insert_game_day(
+        &self,
+        season: SeasonNumber,
+        day: u8,
+        start_time: DateTime<Utc>,
+    ) -> Result<()> {
+        let key = SeasonDayKey::new(season, day);
+        self.days
+            .insert(key.as_bytes(), start_time.to_rfc3339().as_bytes())?;
+        self.inverse_days
+            .insert(TimeKey::new(&start_time).as_bytes(), key.as_bytes())?;
+        Ok(())
+    }
+
+    /// every cached game day, in order
+    pub fn game_days(&self) -> Result<Vec<(SeasonNumber, u8)>> {
+        self.days
+            .iter()
+            .keys()
+            .map(|key| {
+                let key = SeasonDayKey::read_from(key?.as_bytes()).unwrap();
+                Ok((key.season(), key.day))
+            })
+            .collect()
+    }
+
+    /// how many game days started from `earlier` up to but not including `later`
+    pub fn game_days_between(
+        &self,
+        earlier: (SeasonNumber, u8),
+        later: (SeasonNumber, u8),
+    ) -> usize {
+        let earlier = SeasonDayKey::new(earlier.0, earlier.1);
+        let later = SeasonDayKey::new(later.0, later.1);
+        if earlier.as_bytes() < later.as_bytes() {
+            self.days
+                .range(earlier.as_bytes()..later.as_bytes())
+                .count()
+        } else {
+            0
+        }
+    }
+
+    pub fn season_has_game_days(&self, season: SeasonNumber) -> Result<bool> {
+        let season: I16<BigEndian> = season.storage().into();
+        Ok(self.days.scan_prefix(season.as_bytes()).next().is_some())
+    }
+
+    /// The season and day `timestamp` falls in, and how many minutes after the day's first game
+    /// started it is. `None` if it's before the first cached game day.
+    pub fn game_day_at(
+        &self,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Result<Option<(SeasonNumber, u8, f32)>> {
+        let (start_time_bytes, season_day_bytes) = match self
+            .inverse_days
+            .get_lt(TimeKey::new(&timestamp).as_bytes())?
+        {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let start_time = TimeKey::parse(&start_time_bytes);
+        let time_since_start_of_game = timestamp - start_time;
+        let fractional_minutes_since_start_of_game =
+            time_since_start_of_game.num_seconds() as f32 / 60_f32;
+
+        let season_day = SeasonDayKey::read_from(season_day_bytes.as_bytes()).unwrap();
+        Ok(Some((
+            season_day.season(),
+            season_day.day,
+            fractional_minutes_since_start_of_game,
+        )))
+    }
+
+    /// when the first cached game day at or after `day` of `season` started, carrying on into
+    /// later seasons if there are no more days cached for this one
+    pub fn start_of_day_from(
+        &self,
+        season: SeasonNumber,
+        day: u8,
+    ) -> Result<Option<DateTime<FixedOffset>>> {
+        let key = SeasonDayKey::new(season, day);
+        Ok(match self.days.range(key.as_bytes()..).next() {
+            Some(entry) => Some(parse_timestamp(&entry?.1)?),
+            None => None,
+        })
+    }
+
+    /// from the start of the season's first day until the start of the next season, so that the
+    /// boards posted during the postseason and election are included
+    pub fn season_bounds(
+        &self,
+        season: SeasonNumber,
+    ) -> Result<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+        let key = SeasonDayKey::new(season, 0);
+        let timestamp_of_first_day = match self.days.get(key.as_bytes())? {
+            Some(start_time) => parse_timestamp(&start_time)?,
+            None => DateTime::parse_from_rfc3339(BEGINNING_OF_TIME)?,
+        };
+        let timestamp_of_next_season = match self.start_of_day_from(season.next(), 0)? {
+            Some(start_time) => start_time,
+            None => DateTime::parse_from_rfc3339(END_OF_TIME)?,
+        };
+
+        Ok((timestamp_of_first_day, timestamp_of_next_season))
+    }
+
+    pub fn insert_idol_board(&self, valid_from: DateTime<Utc>, board: &IdolsClass) -> Result<()> {
+        self.idols.insert(
+            TimeKey::new(&valid_from).as_bytes(),
+            serde_json::to_vec(board)?,
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_idol_board(&self, valid_from: DateTime<Utc>) -> Result<()> {
+        self.idols.remove(TimeKey::new(&valid_from).as_bytes())?;
+        Ok(())
+    }
+
+    pub fn insert_raw_idol_board(
+        &self,
+        valid_from: DateTime<Utc>,
+        board: &IdolsClass,
+    ) -> Result<()> {
+        self.idols_raw.insert(
+            TimeKey::new(&valid_from).as_bytes(),
+            serde_json::to_vec(board)?,
+        )?;
+        Ok(())
+    }
+
+    /// every board, oldest first
+    pub fn idol_boards(&self) -> impl Iterator<Item = Result<(DateTime<FixedOffset>, IdolsClass)>> {
+        self.idols.iter().map(|entry| parse_board(entry?))
+    }
+
+    /// the boards posted from `first` to `last`, both included
+    pub fn idol_boards_between(
+        &self,
+        first: DateTime<FixedOffset>,
+        last: DateTime<FixedOffset>,
+    ) -> impl Iterator<Item = Result<(DateTime<FixedOffset>, IdolsClass)>> {
+        self.idols
+            .range(TimeKey::new(&first).as_bytes()..=TimeKey::new(&last).as_bytes())
+            .map(|entry| parse_board(entry?))
+    }
+
+    /// when each board from `start` up to but not including `end` was posted
+    pub fn idol_board_times(
+        &self,
+        start: DateTime<FixedOffset>,
+        end: DateTime<FixedOffset>,
+    ) -> Result<Vec<DateTime<FixedOffset>>> {
+        self.idols
+            .range(TimeKey::new(&start).as_bytes()..TimeKey::new(&end).as_bytes())
+            .keys()
+            .map(|key| Ok(TimeKey::parse(&key?)))
+            .collect()
+    }
+
+    /// the board that was showing at `timestamp`, i.e. the latest one posted at or before it
+    pub fn idol_board_at(
+        &self,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Result<Option<(DateTime<FixedOffset>, IdolsClass)>> {
+        let key = TimeKey::new(&timestamp);
+        // get_lt alone would skip a board posted at exactly `timestamp`
+        let entry = match self.idols.get(key.as_bytes())? {
+            Some(idols_bytes) => Some((IVec::from(key.as_bytes()), idols_bytes)),
+            None => self.idols.get_lt(key.as_bytes())?,
+        };
+        entry.map(parse_board).transpose()
+    }
+
+    /// the last board posted before `timestamp`
+    pub fn idol_board_before(
+        &self,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Result<Option<(DateTime<FixedOffset>, IdolsClass)>> {
+        self.idols
+            .get_lt(TimeKey::new(&timestamp).as_bytes())?
+            .map(parse_board)
+            .transpose()
+    }
+
+    /// the first board posted after `timestamp`
+    pub fn idol_board_after(
+        &self,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Result<Option<(DateTime<FixedOffset>, IdolsClass)>> {
+        self.idols
+            .get_gt(TimeKey::new(&timestamp).as_bytes())?
+            .map(parse_board)
+            .transpose()
+    }
+
+    pub fn insert_player_version(
+        &self,
+        id: Uuid,
+        valid_from: DateTime<Utc>,
+        player: &PlayerData,
+    ) -> Result<()> {
+        self.players.insert(
+            Key::new(id, valid_from).as_bytes(),
+            serde_json::to_vec(player)?,
+        )?;
+        Ok(())
+    }
+
+    /// the version of a player that was current at `timestamp`
+    pub fn player_at(&self, id: Uuid, timestamp: DateTime<FixedOffset>) -> Result<PlayerData> {
+        match version_before(&self.players, id, timestamp)? {
+            Some((_, player)) => Ok(player),
+            None => Err(LookupError::UnknownPlayer(id).into()),
+        }
+    }
+
+    /// every version of a player, oldest first
+    pub fn player_versions(&self, id: Uuid) -> Result<Vec<(DateTime<Utc>, PlayerData)>> {
+        self.players
+            .scan_prefix(id.as_bytes())
+            .map(|entry| parse_version(entry?))
+            .collect()
+    }
+
+    /// every version of every player, grouped by player and oldest first within each
+    pub fn all_player_versions(&self) -> impl Iterator<Item = Result<(DateTime<Utc>, PlayerData)>> {
+        self.players.iter().map(|entry| parse_version(entry?))
+    }
+
+    pub fn latest_player_version_time(&self, id: Uuid) -> Result<Option<DateTime<Utc>>> {
+        let end_of_time = DateTime::parse_from_rfc3339(END_OF_TIME)?;
+        Ok(
+            version_before::<PlayerData>(&self.players, id, end_of_time)?
+                .map(|(valid_from, _)| valid_from),
+        )
+    }
+
+    pub fn ingest_checkpoint(&self, id: Uuid) -> Result<Option<IngestCheckpoint>> {
+        Ok(match self.player_ingest.get(id.as_bytes())? {
+            Some(bytes) => Some(serde_json::from_slice(&bytes)?),
+            None => None,
+        })
+    }
+
+    pub fn set_ingest_checkpoint(&self, id: Uuid, checkpoint: &IngestCheckpoint) -> Result<()> {
+        self.player_ingest
+            .insert(id.as_bytes(), serde_json::to_vec(checkpoint)?)?;
+        Ok(())
+    }
+
+    pub fn team_ingest_checkpoint(&self) -> Result<Option<IngestCheckpoint>> {
+        Ok(match self.team_ingest.get(TEAM_INGEST_KEY)? {
+            Some(bytes) => Some(serde_json::from_slice(&bytes)?),
+            None => None,
+        })
+    }
+
+    pub fn set_team_ingest_checkpoint(&self, checkpoint: &IngestCheckpoint) -> Result<()> {
+        self.team_ingest
+            .insert(TEAM_INGEST_KEY, serde_json::to_vec(checkpoint)?)?;
+        Ok(())
+    }
+
+    pub fn insert_team_version(&self, valid_from: DateTime<Utc>, team: &TeamData) -> Result<()> {
+        self.teams.insert(
+            Key::new(team.id, valid_from).as_bytes(),
+            serde_json::to_vec(team)?,
+        )?;
+        Ok(())
+    }
+
+    /// the version of a team that was current at `timestamp`, if there's one that early
+    pub fn team_at(&self, id: Uuid, timestamp: DateTime<FixedOffset>) -> Result<Option<TeamData>> {
+        Ok(version_before(&self.teams, id, timestamp)?.map(|(_, team)| team))
+    }
+
+    /// The team as it was at `timestamp`. Chronicler's history of some teams starts after the
+    /// earliest boards, so before that this is the first version there is. `None` if the team
+    /// isn't cached at all.
+    pub fn team(&self, id: Uuid, timestamp: DateTime<FixedOffset>) -> Result<Option<TeamData>> {
+        if let Some(team) = self.team_at(id, timestamp)? {
+            return Ok(Some(team));
+        }
+
+        Ok(match self.teams.scan_prefix(id.as_bytes()).next() {
+            Some(entry) => Some(serde_json::from_slice(entry?.1.as_bytes())?),
+            None => None,
+        })
+    }
+
+    /// `None` if the player has no versions, or the index hasn't been built
+    pub fn player_index_entry(&self, id: Uuid) -> Result<Option<PlayerIndexEntry>> {
+        Ok(match self.player_index.get(id.as_bytes())? {
+            Some(bytes) => Some(serde_json::from_slice(&bytes)?),
+            None => None,
+        })
+    }
+
+    pub fn player_index(&self) -> impl Iterator<Item = Result<(Uuid, PlayerIndexEntry)>> {
+        self.player_index.iter().map(|entry| {
+            let (id_bytes, entry_bytes) = entry?;
+            Ok((
+                Uuid::from_slice(&id_bytes)?,
+                serde_json::from_slice(&entry_bytes)?,
+            ))
+        })
+    }
+
+    /// Swaps the whole index for `entries` in one batch, so searches made while it's rebuilt see
+    /// either the old index or the new one, and a failure partway through leaves the old one.
+    pub fn replace_player_index<'a>(
+        &self,
+        entries: impl Iterator<Item = (&'a Uuid, &'a PlayerIndexEntry)>,
+    ) -> Result<()> {
+        replace_index(&self.player_index, entries)
+    }
+
+    /// `None` if none of the team's players have been on a board, or the index hasn't been built
+    pub fn team_index(&self, id: Uuid) -> Result<Option<TeamIndexEntry>> {
+        Ok(match self.team_index.get(id.as_bytes())? {
+            Some(bytes) => Some(serde_json::from_slice(&bytes)?),
+            None => None,
+        })
+    }
+
+    /// swaps the whole index for `entries` in one batch, like [`Store::replace_player_index`]
+    pub fn replace_team_index<'a>(
+        &self,
+        entries: impl Iterator<Item = (&'a Uuid, &'a TeamIndexEntry)>,
+    ) -> Result<()> {
+        replace_index(&self.team_index, entries)
+    }
+}
+
+/// replaces everything in a tree keyed by id with `entries`, in a single batch
+fn replace_index<'a, T: Serialize + 'a>(
+    tree: &Tree,
+    entries: impl Iterator<Item = (&'a Uuid, &'a T)>,
+) -> Result<()> {
+    let mut batch = sled::Batch::default();
+    let mut ids = HashSet::new();
+    for (id, entry) in entries {
+        batch.insert(id.as_bytes(), serde_json::to_vec(entry)?);
+        ids.insert(*id);
+    }
+    for id in tree.iter().keys() {
+        let id = id?;
+        if !ids.contains(&Uuid::from_slice(&id)?) {
+            batch.remove(id);
+        }
+    }
+
+    tree.apply_batch(batch)?;
+    Ok(())
+}
+
+fn open_db(path: &Path) -> Result<Db> {
+    sled::Config::default()
+        .path(path)
+        .use_compression(true)
+        .open()
+        .with_context(|| format!("failed to open the database at {}", path.display()))
+}
+
+fn parse_timestamp(bytes: &[u8]) -> Result<DateTime<FixedOffset>> {
+    Ok(DateTime::parse_from_rfc3339(std::str::from_utf8(bytes)?)?)
+}
+
+fn parse_board((key, idols_bytes): (IVec, IVec)) -> Result<(DateTime<FixedOffset>, IdolsClass)> {
+    let idols: Idols = serde_json::from_slice(&idols_bytes)?;
+    Ok((TimeKey::parse(&key), IdolsClass::from(idols)))
+}
+
+fn parse_version<T: serde::de::DeserializeOwned>(
+    (key_bytes, value_bytes): (IVec, IVec),
+) -> Result<(DateTime<Utc>, T)> {
+    let key = Key::read_from(key_bytes.as_bytes()).unwrap();
+    Ok((
+        Utc.timestamp_nanos(key.valid_from.get()),
+        serde_json::from_slice(value_bytes.as_bytes())?,
+    ))
+}
+
+/// the newest version of `id` in a `Key`ed tree from before `timestamp`
+fn version_before<T: serde::de::DeserializeOwned>(
+    tree: &Tree,
+    id: Uuid,
+    timestamp: DateTime<FixedOffset>,
+) -> Result<Option<(DateTime<Utc>, T)>> {
+    match tree.get_lt(Key::new(id, timestamp).as_bytes())? {
+        // the closest key might belong to the entity before this one in the tree
+        Some(entry)
+            if Key::read_from(entry.0.as_bytes()).map(|key| key.id) == Some(*id.as_bytes()) =>
+        {
+            Ok(Some(parse_version(entry)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+#[derive(AsBytes, FromBytes)]
+#[repr(C)]
+pub struct SeasonDayKey {
+    season: I16<BigEndian>,
+    day: u8,
+}
+
+impl SeasonDayKey {
+    fn new(season: SeasonNumber, day: u8) -> SeasonDayKey {
+        SeasonDayKey {
+            season: season.storage().into(),
+            day,
+        }
+    }
+
+    fn season(&self) -> SeasonNumber {
+        SeasonNumber::from_storage(self.season.get())
+    }
+}
+
+/// When a board was posted or a game day started, in nanoseconds, so that the keys sort in time
+/// order however the timestamp was written.
+#[derive(AsBytes, FromBytes)]
+#[repr(C)]
+struct TimeKey {
+    nanos: I64<BigEndian>,
+}
+
+impl TimeKey {
+    fn new<T: TimeZone>(timestamp: &DateTime<T>) -> TimeKey {
+        TimeKey {
+            nanos: timestamp.timestamp_nanos().into(),
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> DateTime<FixedOffset> {
+        let key = TimeKey::read_from(bytes).unwrap();
+        Utc.timestamp_nanos(key.nanos.get()).into()
+    }
+}
+
+/// A version of a player or team: the id, then when the version became current in nanoseconds,
+/// so that each entity's versions sort together and in time order.
+#[derive(AsBytes, FromBytes)]
+#[repr(C)]
+struct Key {
+    id: [u8; 16],
+    valid_from: I64<BigEndian>,
+}
+
+impl Key {
+    fn new<T: chrono::TimeZone>(id: Uuid, valid_from: DateTime<T>) -> Key {
+        Key {
+            id: *id.as_bytes(),
+            valid_from: valid_from.timestamp_nanos().into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{at, board, player, team};
+
+    #[test]
+    fn season_day_key_stores_chronicler_numbering() {
+        let season = SeasonNumber::from_display(6).unwrap();
+        let key = SeasonDayKey::new(season, 0);
+        assert_eq!(key.as_bytes(), [0, 5, 0]);
+        assert_eq!(
+            SeasonDayKey::read_from(key.as_bytes()).unwrap().season(),
+            season
+        );
+    }
+
+    #[test]
+    fn season_day_keys_sort_by_season_then_day() {
+        let season = SeasonNumber::from_storage(5);
+        let last_day = SeasonDayKey::new(season, u8::MAX);
+        let next_season = SeasonDayKey::new(season.next(), 0);
+        assert!(SeasonDayKey::new(season, 0).as_bytes() < last_day.as_bytes());
+        assert!(last_day.as_bytes() < next_season.as_bytes());
+    }
+
+    #[test]
+    fn player_at_finds_the_version_current_at_the_time() {
+        let store = Store::temporary().unwrap();
+        let (first, second) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        for (id, valid_from, name) in [
+            (first, "2020-08-01T00:00:00Z", "Old Name"),
+            (first, "2020-09-01T00:00:00Z", "New Name"),
+            (second, "2020-07-01T00:00:00Z", "Neighbour"),
+        ] {
+            store
+                .insert_player_version(id, at(valid_from).into(), &player(id, name))
+                .unwrap();
+        }
+
+        let name_at = |id, timestamp| store.player_at(id, at(timestamp)).unwrap().name;
+        assert_eq!(name_at(first, "2020-08-15T00:00:00Z"), "Old Name");
+        assert_eq!(name_at(first, "2021-01-01T00:00:00Z"), "New Name");
+        assert_eq!(name_at(second, "2021-01-01T00:00:00Z"), "Neighbour");
+        // the neighbouring player's versions sort just before this one's
+        for (id, timestamp) in [
+            (second, "2020-06-01T00:00:00Z"),
+            (first, "2020-07-15T00:00:00Z"),
+        ] {
+            match store.player_at(id, at(timestamp)) {
+                Err(err) => assert!(err.downcast_ref::<LookupError>().is_some()),
+                Ok(_) => panic!("found a version of {} from before it was first seen", id),
+            }
+        }
+    }
+
+    #[test]
+    fn team_falls_back_to_the_earliest_version() {
+        let store = Store::temporary().unwrap();
+        let id = Uuid::from_u128(3);
+        store
+            .insert_team_version(at("2020-09-01T00:00:00Z").into(), &team(id, "Llamas"))
+            .unwrap();
+
+        let early = at("2020-08-01T00:00:00Z");
+        assert!(store.team_at(id, early).unwrap().is_none());
+        assert_eq!(store.team(id, early).unwrap().unwrap().full_name, "Llamas");
+        assert!(store.team(Uuid::from_u128(4), early).unwrap().is_none());
+    }
+
+    #[test]
+    fn time_keys_sort_in_time_order_whatever_the_offset() {
+        let earlier = at("2020-08-01T01:00:00.5+02:00");
+        let later = at("2020-08-01T00:00:00Z");
+        assert!(TimeKey::new(&earlier).as_bytes() < TimeKey::new(&later).as_bytes());
+        assert_eq!(TimeKey::parse(TimeKey::new(&earlier).as_bytes()), earlier);
+    }
+
+    #[test]
+    fn game_day_at_counts_minutes_since_the_day_started() {
+        let store = Store::temporary().unwrap();
+        let season = SeasonNumber::from_storage(5);
+        store
+            .insert_game_day(season, 3, at("2020-09-07T16:00:00Z").into())
+            .unwrap();
+
+        assert_eq!(store.game_day_at(at("2020-09-07T15:00:00Z")).unwrap(), None);
+        assert_eq!(
+            store.game_day_at(at("2020-09-07T16:05:30Z")).unwrap(),
+            Some((season, 3, 5.5))
+        );
+    }
+
+    #[test]
+    fn replacing_the_player_index_drops_players_no_longer_in_it() {
+        let store = Store::temporary().unwrap();
+        let entry = |name: &str| PlayerIndexEntry {
+            names: vec![name.to_string()],
+            ..Default::default()
+        };
+        let (first, second, third) = (Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3));
+        store
+            .replace_player_index(
+                [(&first, &entry("First")), (&second, &entry("Second"))].into_iter(),
+            )
+            .unwrap();
+        store
+            .replace_player_index(
+                [(&second, &entry("Renamed")), (&third, &entry("Third"))].into_iter(),
+            )
+            .unwrap();
+
+        let index = store
+            .player_index()
+            .map(|entry| {
+                let (id, entry) = entry.unwrap();
+                (id, entry.names)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            index,
+            [
+                (second, vec!["Renamed".to_string()]),
+                (third, vec!["Third".to_string()])
+            ]
+        );
+    }
+
+    #[test]
+    fn idol_board_at_includes_a_board_posted_at_that_time() {
+        let store = Store::temporary().unwrap();
+        let (first, second) = (Uuid::from_u128(1), Uuid::from_u128(2));
+        store
+            .insert_idol_board(at("2020-08-01T00:00:00Z").into(), &board(&[first]))
+            .unwrap();
+        store
+            .insert_idol_board(at("2020-08-02T00:00:00Z").into(), &board(&[second]))
+            .unwrap();
+
+        let idols_at = |timestamp| {
+            store
+                .idol_board_at(at(timestamp))
+                .unwrap()
+                .map(|(_, board)| board.idols)
+        };
+        assert_eq!(idols_at("2020-07-31T00:00:00Z"), None);
+        assert_eq!(idols_at("2020-08-01T12:00:00Z"), Some(vec![first]));
+        assert_eq!(idols_at("2020-08-02T00:00:00Z"), Some(vec![second]));
+    }
+}
@@ -0,0 +1,41 @@
+//! Fixtures shared by the test modules.
+
+use crate::entities::idol::IdolsClass;
+use crate::entities::player::PlayerData;
+use crate::entities::team::TeamData;
+use chrono::{DateTime, FixedOffset};
+use uuid::Uuid;
+
+pub fn at(timestamp: &str) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(timestamp).unwrap()
+}
+
+/// a living player without a team or any permanent attributes
+pub fn player(id: Uuid, name: &str) -> PlayerData {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "name": name,
+        "leagueTeamId": null,
+        "deceased": false,
+        "permAttr": null,
+    }))
+    .unwrap()
+}
+
+/// a team with nobody on its roster
+pub fn team(id: Uuid, name: &str) -> TeamData {
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "fullName": name,
+        "mainColor": "#000000",
+        "secondaryColor": null,
+        "emoji": "0x1F999",
+        "slogan": null,
+        "card": null,
+    }))
+    .unwrap()
+}
+
+pub fn board(idols: &[Uuid]) -> IdolsClass {
+    serde_json::from_value(serde_json::json!({ "idols": idols })).unwrap()
+}